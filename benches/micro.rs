@@ -0,0 +1,106 @@
+// Micro-benchmarks for the pieces of the CPU mining loop.
+// Run with `cargo bench --bench micro`.
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use num_bigint::BigUint;
+use secp256k1::hashes::{sha256, Hash};
+use secp256k1::rand::rngs::OsRng;
+use secp256k1::rand::RngCore;
+use secp256k1::Secp256k1;
+
+// The miner is a binary crate, so pull the modules in directly. Their unit
+// tests are compiled out of the harness-less bench.
+#[allow(dead_code, unused_imports)]
+#[path = "../src/hasher.rs"]
+mod hasher;
+#[allow(dead_code, unused_imports)]
+#[path = "../src/hash256.rs"]
+mod hash256;
+
+use hasher::{Backend, BatchHasher, PUBKEY_LEN};
+use hash256::{Hash256, Target};
+
+const SEED: &str = "3f7a1c9e5b2d8a6f4e0c7b1d9a3e5f2c";
+
+fn random_hash() -> [u8; 32] {
+    let mut hash = [0u8; 32];
+    OsRng.fill_bytes(&mut hash);
+    hash
+}
+
+fn key_generation(c: &mut Criterion) {
+    let secp = Secp256k1::new();
+    let mut rng = OsRng;
+    c.bench_function("generate_keypair", |b| b.iter(|| secp.generate_keypair(&mut rng)));
+}
+
+fn pubkey_hex(c: &mut Criterion) {
+    let secp = Secp256k1::new();
+    let (_, public_key) = secp.generate_keypair(&mut OsRng);
+    c.bench_function("pubkey_hex_encode", |b| {
+        b.iter(|| hex::encode(black_box(public_key).serialize_uncompressed()))
+    });
+}
+
+fn seed_hash(c: &mut Criterion) {
+    let secp = Secp256k1::new();
+    let (_, public_key) = secp.generate_keypair(&mut OsRng);
+    let public_key_hex = hex::encode(public_key.serialize_uncompressed());
+
+    let mut group = c.benchmark_group("sha256_pubkey_seed");
+    group.throughput(Throughput::Elements(1));
+    group.bench_function("string_concat", |b| {
+        b.iter(|| sha256::Hash::hash(format!("{}{}", black_box(&public_key_hex), SEED).as_bytes()))
+    });
+
+    // Per-hash cost of each batch backend this CPU supports
+    let pubkeys: Vec<[u8; PUBKEY_LEN]> = (0..64)
+        .map(|_| secp.generate_keypair(&mut OsRng).1.serialize_uncompressed())
+        .collect();
+    let mut out = vec![[0u8; 32]; pubkeys.len()];
+    group.throughput(Throughput::Elements(pubkeys.len() as u64));
+    for backend in [Backend::Scalar, Backend::Sse2, Backend::Avx2, Backend::Avx512, Backend::ShaNi] {
+        if !backend.is_supported() {
+            continue;
+        }
+        let mut hasher = BatchHasher::new(backend);
+        hasher.set_seed(SEED);
+        group.bench_function(format!("batch_{}", backend), |b| {
+            b.iter(|| hasher.hash(black_box(&pubkeys), &mut out))
+        });
+    }
+    group.finish();
+}
+
+fn target_compare(c: &mut Criterion) {
+    let target = Target(random_hash());
+    let target_big = BigUint::from_bytes_be(&target.0);
+
+    let mut group = c.benchmark_group("target_compare");
+    group.bench_function("biguint", |b| {
+        b.iter_batched(
+            random_hash,
+            |hash| target_big >= BigUint::from_bytes_be(&hash),
+            BatchSize::SmallInput,
+        )
+    });
+    group.bench_function("fixed_width", |b| {
+        b.iter_batched(random_hash, |hash| Hash256(hash).meets(&target), BatchSize::SmallInput)
+    });
+    group.finish();
+}
+
+fn hex_256(c: &mut Criterion) {
+    let hash = Hash256(random_hash());
+    let mut group = c.benchmark_group("hex_256");
+    group.bench_function("display", |b| b.iter(|| black_box(hash).to_string()));
+    group.bench_function("parse", |b| {
+        let hex = hash.to_string();
+        b.iter(|| Hash256::from_hex(black_box(&hex)))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, key_generation, pubkey_hex, seed_hash, target_compare, hex_256);
+criterion_main!(benches);
@@ -0,0 +1,66 @@
+use std::time::Duration;
+
+/// Exponential backoff with jitter. Each failure doubles the delay up to
+/// `max`, and the actual wait is drawn from the upper half of it so many
+/// miners that lost the same daemon don't all come back at once.
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+    attempt: u32,
+}
+
+impl Backoff {
+    pub fn new(initial: Duration, max: Duration) -> Self {
+        Backoff { initial, max: max.max(initial), attempt: 0 }
+    }
+
+    pub fn next_delay(&mut self) -> Duration {
+        let delay = self.initial.saturating_mul(1 << self.attempt.min(16)).min(self.max);
+        self.attempt = self.attempt.saturating_add(1);
+        delay.mul_f64(0.5 + rand::random::<f64>() * 0.5)
+    }
+
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The jittered delay lies in the upper half of the nominal one
+    fn assert_nominal(delay: Duration, nominal: Duration) {
+        assert!(delay >= nominal / 2 && delay <= nominal, "{:?} isn't within half of {:?}", delay, nominal);
+    }
+
+    #[test]
+    fn delays_double_up_to_the_max() {
+        let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(10));
+        for nominal in [1, 2, 4, 8, 10, 10, 10] {
+            assert_nominal(backoff.next_delay(), Duration::from_secs(nominal));
+        }
+        // Stays capped long after the shift would overflow
+        for _ in 0..100 {
+            assert_nominal(backoff.next_delay(), Duration::from_secs(10));
+        }
+    }
+
+    #[test]
+    fn reset_starts_over() {
+        let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(60));
+        for _ in 0..5 {
+            backoff.next_delay();
+        }
+        backoff.reset();
+        assert_nominal(backoff.next_delay(), Duration::from_secs(1));
+        assert_nominal(backoff.next_delay(), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn max_below_initial_is_raised_to_it() {
+        let mut backoff = Backoff::new(Duration::from_secs(5), Duration::from_secs(1));
+        assert_nominal(backoff.next_delay(), Duration::from_secs(5));
+        assert_nominal(backoff.next_delay(), Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,251 @@
+use colored::*;
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+use serde::Serialize;
+
+use crate::config::CLCMinerConfig;
+use crate::cpu::{CpuMinerOptions, CpuMiningPool};
+use crate::get_job::{Job, JobPublisher};
+use crate::gpu::GPUMiningPool;
+use crate::hash256::Target;
+use crate::rng;
+use crate::stats::MinerStats;
+
+// Fixed so every bench run hashes the same message
+const BENCH_SEED: &str = "clc-miner-bench";
+
+#[derive(Debug, Serialize)]
+pub struct BenchReport {
+    pub seconds: f64,
+    pub cpu: Option<CpuBench>,
+    pub gpu: Option<GpuBench>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CpuBench {
+    pub threads: usize,
+    pub hasher: String,
+    pub endomorphism: bool,
+    pub hashes: u64,
+    // With endomorphism every key is hashed as several candidates
+    pub hashes_per_sec: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GpuBench {
+    pub devices: usize,
+    pub batches: usize,
+    pub hashes: u64,
+    pub hashes_per_sec: f64,
+    pub batch_latency_ms: LatencyPercentiles,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LatencyPercentiles {
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+    pub max: f64,
+}
+
+/// Mines a synthetic job that can never be solved with the configured CPU
+/// threads and GPU devices for `seconds`, then reports the throughput of each.
+/// Nothing is fetched from or sent to the daemon.
+pub async fn run(config: &CLCMinerConfig, seconds: u64, json: bool, deterministic_seed: Option<u64>) {
+    let duration = Duration::from_secs(seconds.max(1));
+    let (thread_num, thread_policy) = crate::build_thread_policy(config);
+    let gpu_shard = thread_num;
+    let stats = Arc::new(MinerStats::new(thread_num + 1));
+
+    let (job_publisher, job_rx) = JobPublisher::new();
+    job_publisher.publish(Job {
+        seed: String::from(BENCH_SEED),
+        diff: Target::ZERO,
+        reward: 0.0,
+        last_found: 0,
+        clock: None,
+    });
+
+    let mut cpu_run = None;
+    if thread_num > 0 {
+        let backend = crate::select_hasher(&config.cpu_hasher);
+        match CpuMiningPool::new(thread_num, thread_policy.clone()) {
+            Ok(pool) => {
+                let (events_tx, events_rx) = mpsc::unbounded_channel();
+                let options = CpuMinerOptions {
+                    endomorphism: config.endomorphism,
+                    backend,
+                    deterministic_seed,
+                };
+                pool.start(job_rx.clone(), options, Arc::clone(&stats), events_tx);
+                cpu_run = Some((pool, events_rx, backend));
+            }
+            Err(e) => {
+                println!("{} Failed to start CPU mining threads: {}", "[ERROR]".red(), e);
+            }
+        }
+    }
+
+    let gpu_handle = if config.gpu > 0 {
+        let devices = config.gpu as usize;
+        let gpu_policy = thread_policy.clone();
+        let runtime = tokio::runtime::Handle::current();
+        // Its own thread so the thread policy doesn't stay on a pooled one
+        let gpu_thread = std::thread::Builder::new().name(String::from("gpu-miner")).spawn(move || {
+            if let Err(e) = gpu_policy.apply(gpu_shard) {
+                println!("{} Failed to apply thread policy to GPU thread: {}", "[WARN]".yellow(), e);
+            }
+            runtime.block_on(bench_gpu(devices, duration, deterministic_seed, gpu_shard))
+        });
+        match gpu_thread {
+            Ok(gpu_thread) => Some(gpu_thread),
+            Err(e) => {
+                println!("{} Failed to start GPU mining thread: {}", "[GPU]".red(), e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    println!(
+        "{} Benchmarking {} CPU threads and {} GPU devices for {}s...",
+        "[INFO]".blue(),
+        thread_num,
+        config.gpu.max(0),
+        duration.as_secs()
+    );
+    let start = Instant::now();
+    let start_hashes = stats.snapshot().hashes;
+    tokio::time::sleep(duration).await;
+    let cpu_hashes = stats.snapshot().hashes - start_hashes;
+    let elapsed = start.elapsed().as_secs_f64();
+    // Parks the CPU workers while the GPU thread finishes its last batch
+    job_publisher.pause(job_publisher.current().epoch);
+
+    let cpu = cpu_run.map(|(_pool, _events, backend)| CpuBench {
+        threads: thread_num,
+        hasher: backend.to_string(),
+        endomorphism: config.endomorphism,
+        hashes: cpu_hashes,
+        hashes_per_sec: cpu_hashes as f64 / elapsed,
+    });
+    let gpu = match gpu_handle {
+        Some(handle) => tokio::task::spawn_blocking(move || handle.join().unwrap_or(None)).await.unwrap_or(None),
+        None => None,
+    };
+
+    let report = BenchReport { seconds: elapsed, cpu, gpu };
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report).unwrap());
+    } else {
+        print_report(&report);
+    }
+}
+
+// Runs GPU batches back to back until `duration` has passed, timing each one.
+async fn bench_gpu(
+    devices: usize,
+    duration: Duration,
+    deterministic_seed: Option<u64>,
+    gpu_shard: usize,
+) -> Option<GpuBench> {
+    let mut pool = match GPUMiningPool::new(devices).await {
+        Ok(pool) => pool,
+        Err(e) => {
+            println!("{} Failed to create GPU pool: {}", "[GPU]".red(), e);
+            return None;
+        }
+    };
+    let keys_per_call = pool.keys_per_call();
+    let mut nonce = rng::key_rng(deterministic_seed, gpu_shard as u64).next_u64();
+    let mut latencies = vec![];
+
+    let start = Instant::now();
+    while start.elapsed() < duration {
+        let batch_start = Instant::now();
+        if let Err(e) = pool.mine_parallel(&Target::ZERO, BENCH_SEED, nonce).await {
+            println!("{} GPU mining error: {}", "[GPU]".red(), e);
+            return None;
+        }
+        latencies.push(batch_start.elapsed());
+        nonce = nonce.wrapping_add(keys_per_call as u64);
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+
+    // Each call hashes a fixed kernel batch per device, whatever gpu_batch_size says
+    let hashes = (latencies.len() * keys_per_call) as u64;
+    latencies.sort();
+    Some(GpuBench {
+        devices: pool.get_active_miners(),
+        batches: latencies.len(),
+        hashes,
+        hashes_per_sec: hashes as f64 / elapsed,
+        batch_latency_ms: LatencyPercentiles {
+            p50: percentile(&latencies, 0.50),
+            p90: percentile(&latencies, 0.90),
+            p99: percentile(&latencies, 0.99),
+            max: percentile(&latencies, 1.0),
+        },
+    })
+}
+
+// Nearest-rank percentile of sorted latencies, in milliseconds
+fn percentile(sorted: &[Duration], p: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let rank = ((p * sorted.len() as f64).ceil() as usize).clamp(1, sorted.len());
+    sorted[rank - 1].as_secs_f64() * 1e3
+}
+
+fn print_report(report: &BenchReport) {
+    println!("\n{} Benchmark finished after {:.1}s", "[INFO]".blue(), report.seconds);
+    if let Some(cpu) = &report.cpu {
+        println!(
+            "{} CPU: {} threads, {} hasher{}: {}",
+            "[INFO]".blue(),
+            cpu.threads,
+            cpu.hasher,
+            if cpu.endomorphism { ", endomorphism" } else { "" },
+            format_rate(cpu.hashes_per_sec).green()
+        );
+    }
+    if let Some(gpu) = &report.gpu {
+        println!(
+            "{} {} devices, {} batches: {}",
+            "[GPU]".green(),
+            gpu.devices,
+            gpu.batches,
+            format_rate(gpu.hashes_per_sec).green()
+        );
+        println!(
+            "{} Batch latency p50 {:.2}ms, p90 {:.2}ms, p99 {:.2}ms, max {:.2}ms",
+            "[GPU]".green(),
+            gpu.batch_latency_ms.p50,
+            gpu.batch_latency_ms.p90,
+            gpu.batch_latency_ms.p99,
+            gpu.batch_latency_ms.max
+        );
+    }
+    if report.cpu.is_none() && report.gpu.is_none() {
+        println!("{} Nothing was benchmarked, check the thread and gpu settings", "[WARN]".yellow());
+    }
+}
+
+fn format_rate(hashes_per_sec: f64) -> String {
+    if hashes_per_sec >= 1e12 {
+        format!("{:.2} TH/s", hashes_per_sec / 1e12)
+    } else if hashes_per_sec >= 1e9 {
+        format!("{:.2} GH/s", hashes_per_sec / 1e9)
+    } else if hashes_per_sec >= 1e6 {
+        format!("{:.2} MH/s", hashes_per_sec / 1e6)
+    } else if hashes_per_sec >= 1e3 {
+        format!("{:.2} KH/s", hashes_per_sec / 1e3)
+    } else {
+        format!("{:.2} H/s", hashes_per_sec)
+    }
+}
@@ -0,0 +1,422 @@
+// A stand-in for clc-daemon for testing the miner end to end without mining
+// real coins. It hands out challenges with a configurable (easy) difficulty
+// and checks submissions the way the daemon does: the signature over
+// sha256(holder) and sha256(holder + seed) against the hash and the diff.
+use clap::Parser;
+use colored::*;
+use secp256k1::ecdsa::Signature;
+use secp256k1::{PublicKey, Secp256k1};
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, UNIX_EPOCH};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::watch;
+
+#[allow(dead_code)]
+#[path = "../hash256.rs"]
+mod hash256;
+#[path = "../verify.rs"]
+mod verify;
+#[allow(dead_code)]
+#[path = "../util.rs"]
+mod util;
+
+use hash256::{Hash256, Target};
+use util::unix_millis;
+
+// Comment lines sent on idle event streams so the miner knows they are alive
+const KEEPALIVE: Duration = Duration::from_secs(15);
+
+#[derive(Parser, Debug)]
+#[command(about = "Local stand-in for clc-daemon")]
+struct Args {
+    #[arg(long, default_value_t = 3000)]
+    port: u16,
+    /// Target as hex, the default takes about 256 hashes per coin
+    #[arg(long, default_value = "00ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff")]
+    diff: String,
+    #[arg(long, default_value_t = 1.0)]
+    reward: f64,
+    /// Replace the challenge every this many seconds even if nobody solves it, 0 never does
+    #[arg(long, default_value_t = 0)]
+    rotate: u64,
+    /// Run the daemon clock this many seconds ahead of the real one (negative is behind)
+    #[arg(long, default_value_t = 0, allow_hyphen_values = true)]
+    clock_offset: i64,
+    /// Act like an old daemon that only takes GET submissions, POST gets a 404 or the given status
+    #[arg(long, num_args = 0..=1, default_missing_value = "404")]
+    get_only: Option<u16>,
+    /// Print every request line and the names of the fields in its body
+    #[arg(long)]
+    log_requests: bool,
+    /// Don't serve the /challenge-events stream, like a daemon that predates it
+    #[arg(long)]
+    no_events: bool,
+    /// Close every event stream after this many seconds, 0 keeps them open
+    #[arg(long, default_value_t = 0)]
+    drop_streams: u64,
+    /// Turn down the first N valid solutions as a bad signature, without a new challenge
+    #[arg(long, default_value_t = 0)]
+    reject_first: u64,
+    /// Credit the first N valid solutions but close the connection without answering
+    #[arg(long, default_value_t = 0)]
+    drop_replies: u64,
+}
+
+struct Coin {
+    holder: String,
+    reward: f64,
+}
+
+struct Daemon {
+    clock_offset_ms: i64,
+    get_only: Option<u16>,
+    log_requests: bool,
+    no_events: bool,
+    drop_streams: u64,
+    reject_first: u64,
+    drop_replies: u64,
+    // Seeds that were solved, for telling a late solution it lost
+    solved: Vec<String>,
+    // The current challenge, for the event streams
+    events: watch::Sender<serde_json::Value>,
+    seed: String,
+    diff: Target,
+    reward: f64,
+    last_found: u64,
+    coins: HashMap<u64, Coin>,
+    next_id: u64,
+}
+
+impl Daemon {
+    fn rotate(&mut self) {
+        self.seed = hex::encode(rand::random::<[u8; 16]>());
+        self.last_found = self.now_ms();
+        self.events.send_replace(self.challenge());
+    }
+
+    fn challenge(&self) -> serde_json::Value {
+        json!({
+            "seed": self.seed,
+            "diff": self.diff.to_string(),
+            "reward": self.reward,
+            "lastFound": self.last_found,
+        })
+    }
+
+    fn http_date(&self) -> String {
+        httpdate::fmt_http_date(UNIX_EPOCH + Duration::from_millis(self.now_ms()))
+    }
+
+    fn now_ms(&self) -> u64 {
+        (unix_millis() as i64 + self.clock_offset_ms) as u64
+    }
+}
+
+struct Response {
+    status: u16,
+    content_type: &'static str,
+    body: String,
+    // Close the connection instead of answering
+    dropped: bool,
+}
+
+impl Response {
+    fn json(value: serde_json::Value) -> Self {
+        Response { status: 200, content_type: "application/json", body: value.to_string(), dropped: false }
+    }
+
+    fn error(status: u16, message: &str) -> Self {
+        Response { status, content_type: "text/plain", body: message.to_string(), dropped: false }
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let args = Args::parse();
+    let diff = match Target::from_hex(&args.diff) {
+        Ok(diff) => diff,
+        Err(e) => {
+            eprintln!("{} {}", "[ERROR]".red(), e);
+            std::process::exit(1);
+        }
+    };
+    let mut daemon = Daemon {
+        clock_offset_ms: args.clock_offset * 1000,
+        get_only: args.get_only,
+        log_requests: args.log_requests,
+        no_events: args.no_events,
+        drop_streams: args.drop_streams,
+        reject_first: args.reject_first,
+        drop_replies: args.drop_replies,
+        solved: vec![],
+        events: watch::Sender::new(json!({})),
+        seed: String::new(),
+        diff,
+        reward: args.reward,
+        last_found: 0,
+        coins: HashMap::new(),
+        next_id: 1,
+    };
+    daemon.rotate();
+    let daemon = Arc::new(Mutex::new(daemon));
+
+    if args.rotate > 0 {
+        let daemon = Arc::clone(&daemon);
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(Duration::from_secs(args.rotate)).await;
+                let mut daemon = daemon.lock().unwrap();
+                daemon.rotate();
+                println!("{} Rotated challenge, seed {}", "[INFO]".blue(), daemon.seed);
+            }
+        });
+    }
+
+    let listener = match TcpListener::bind(("127.0.0.1", args.port)).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("{} Can't listen on port {}: {}", "[ERROR]".red(), args.port, e);
+            std::process::exit(1);
+        }
+    };
+    println!("{} Mock daemon on http://127.0.0.1:{}, diff {} ({} leading zero bits)", "[INFO]".blue(), args.port, diff, diff.leading_zero_bits());
+
+    loop {
+        let (stream, _) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(e) => {
+                eprintln!("{} Accept failed: {}", "[ERROR]".red(), e);
+                continue;
+            }
+        };
+        let daemon = Arc::clone(&daemon);
+        tokio::spawn(async move {
+            if let Err(e) = serve(stream, daemon).await {
+                eprintln!("{} Connection failed: {}", "[ERROR]".red(), e);
+            }
+        });
+    }
+}
+
+// One request per connection, which is all the miner needs
+async fn serve(mut stream: TcpStream, daemon: Arc<Mutex<Daemon>>) -> std::io::Result<()> {
+    let mut buffer = vec![];
+    let head_end = loop {
+        if let Some(end) = buffer.windows(4).position(|window| window == b"\r\n\r\n") {
+            break end + 4;
+        }
+        let mut chunk = [0u8; 4096];
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 {
+            return Ok(());
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    };
+    let head = String::from_utf8_lossy(&buffer[..head_end]).to_string();
+    let mut lines = head.lines();
+    let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+    let method = request_line.next().unwrap_or_default().to_string();
+    let target = request_line.next().unwrap_or_default().to_string();
+    let content_length = lines
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.trim().parse::<usize>().ok())
+        .unwrap_or(0);
+    while buffer.len() < head_end + content_length {
+        let mut chunk = [0u8; 4096];
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 {
+            break;
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    }
+    let body = String::from_utf8_lossy(&buffer[head_end..]).to_string();
+    if daemon.lock().unwrap().log_requests {
+        print_request(&method, &target, &body);
+    }
+
+    let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+    if path == "/challenge-events" && !daemon.lock().unwrap().no_events {
+        return stream_events(stream, &daemon).await;
+    }
+    let mut params = parse_form(query);
+    if method == "POST" {
+        match serde_json::from_str::<HashMap<String, serde_json::Value>>(&body) {
+            Ok(fields) => params.extend(fields.into_iter().map(|(key, value)| match value {
+                serde_json::Value::String(value) => (key, value),
+                value => (key, value.to_string()),
+            })),
+            Err(_) => params.extend(parse_form(&body)),
+        }
+    }
+
+    let response = route(&method, path, &params, &daemon);
+    if response.dropped {
+        return Ok(());
+    }
+    let date = daemon.lock().unwrap().http_date();
+    let reason = match response.status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        501 => "Not Implemented",
+        _ => "Error",
+    };
+    let out = format!(
+        "HTTP/1.1 {} {}\r\nDate: {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.status,
+        reason,
+        date,
+        response.content_type,
+        response.body.len(),
+        response.body
+    );
+    stream.write_all(out.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+fn route(method: &str, path: &str, params: &HashMap<String, String>, daemon: &Mutex<Daemon>) -> Response {
+    let mut daemon = daemon.lock().unwrap();
+    match path {
+        "/get-challenge" => Response::json(daemon.challenge()),
+        "/challenge-solved" if method == "POST" && daemon.get_only.is_some() => {
+            Response::error(daemon.get_only.unwrap_or(404), "POST submissions not supported")
+        }
+        "/challenge-solved" => {
+            let field = |name: &str| params.get(name).map(String::as_str).unwrap_or_default();
+            match verify(field("holder"), field("sign"), field("hash"), &daemon.seed, &daemon.diff) {
+                Ok(()) if daemon.reject_first > 0 => {
+                    daemon.reject_first -= 1;
+                    println!("{} Turning down a valid solution, {} more to go", "[WARN]".yellow(), daemon.reject_first);
+                    Response::error(400, "Invalid signature")
+                }
+                Ok(()) => {
+                    let id = daemon.next_id;
+                    daemon.next_id += 1;
+                    let coin = Coin { holder: field("holder").to_string(), reward: daemon.reward };
+                    daemon.coins.insert(id, coin);
+                    let seed = daemon.seed.clone();
+                    daemon.solved.push(seed);
+                    daemon.rotate();
+                    println!("{} {} {} solved the challenge, coin {}", "[INFO]".blue(), method, &field("holder")[..16.min(field("holder").len())], id);
+                    if daemon.drop_replies > 0 {
+                        daemon.drop_replies -= 1;
+                        println!("{} Dropping the reply for coin {} to {}", "[WARN]".yellow(), id, field("holder"));
+                        return Response { dropped: true, ..Response::json(json!({})) };
+                    }
+                    Response::json(json!({ "id": id }))
+                }
+                // A valid solution for a challenge someone already solved
+                Err(_) if daemon.solved.iter().any(|seed| verify(field("holder"), field("sign"), field("hash"), seed, &daemon.diff).is_ok()) => {
+                    println!("{} Late solution for a solved challenge", "[WARN]".yellow());
+                    Response::error(400, "Challenge already solved")
+                }
+                Err(e) => {
+                    println!("{} Rejected solution: {}", "[WARN]".yellow(), e);
+                    Response::error(400, &e)
+                }
+            }
+        }
+        "/report" => {
+            println!(
+                "{} Report from {}: speed {}, best {}, mined {}, expected time {}, luck {}",
+                "[INFO]".blue(),
+                params.get("user").map(String::as_str).unwrap_or("?"),
+                params.get("speed").map(String::as_str).unwrap_or("?"),
+                params.get("best").map(String::as_str).unwrap_or("?"),
+                params.get("mined").map(String::as_str).unwrap_or("?"),
+                params.get("expected_time").map(String::as_str).unwrap_or("?"),
+                params.get("luck").map(String::as_str).unwrap_or("?"),
+            );
+            Response::json(json!({}))
+        }
+        _ => match path.strip_prefix("/coin/").and_then(|id| id.parse::<u64>().ok()) {
+            Some(id) => match daemon.coins.get(&id) {
+                Some(coin) => Response::json(json!({ "id": id, "holder": coin.holder, "reward": coin.reward })),
+                None => Response::error(404, "No such coin"),
+            },
+            None => Response::error(404, "Not found"),
+        },
+    }
+}
+
+// Pushes the challenge as a server-sent event now and whenever it changes,
+// with keepalive comments in between
+async fn stream_events(mut stream: TcpStream, daemon: &Mutex<Daemon>) -> std::io::Result<()> {
+    let (mut events, drop_after, date) = {
+        let daemon = daemon.lock().unwrap();
+        (daemon.events.subscribe(), daemon.drop_streams, daemon.http_date())
+    };
+    let head = format!(
+        "HTTP/1.1 200 OK\r\nDate: {}\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n",
+        date
+    );
+    stream.write_all(head.as_bytes()).await?;
+    println!("{} Event stream opened", "[INFO]".blue());
+    let dropped = async {
+        match drop_after {
+            0 => std::future::pending().await,
+            secs => tokio::time::sleep(Duration::from_secs(secs)).await,
+        }
+    };
+    tokio::pin!(dropped);
+    loop {
+        let challenge = events.borrow_and_update().clone();
+        stream.write_all(format!("data: {}\n\n", challenge).as_bytes()).await?;
+        loop {
+            tokio::select! {
+                changed = events.changed() => match changed {
+                    Ok(()) => break,
+                    Err(_) => return Ok(()),
+                },
+                _ = tokio::time::sleep(KEEPALIVE) => stream.write_all(b": keepalive\n\n").await?,
+                _ = &mut dropped => {
+                    println!("{} Dropping the event stream", "[INFO]".blue());
+                    return stream.shutdown().await;
+                }
+            }
+        }
+    }
+}
+
+// The same checks the daemon makes before it hands out a coin
+fn verify(holder: &str, sign: &str, hash: &str, seed: &str, diff: &Target) -> Result<(), String> {
+    let holder_bytes = hex::decode(holder).map_err(|_| String::from("Invalid holder"))?;
+    let public_key = PublicKey::from_slice(&holder_bytes).map_err(|_| String::from("Invalid holder"))?;
+    let signature = hex::decode(sign)
+        .ok()
+        .and_then(|der| Signature::from_der(&der).ok())
+        .ok_or_else(|| String::from("Invalid signature"))?;
+    let hash = Hash256::from_hex(hash).map_err(|_| String::from("Invalid hash"))?;
+    verify::verify_solution(&Secp256k1::verification_only(), &public_key, &signature, &hash, seed, diff)
+        .map_err(|e| match e {
+            verify::VerifyError::InvalidSignature => String::from("Invalid signature"),
+            verify::VerifyError::HashMismatch { .. } => String::from("Invalid hash"),
+            verify::VerifyError::AboveTarget => String::from("Hash is above the difficulty"),
+        })
+}
+
+// Field values stay out of the log, the names show where secrets were sent
+fn print_request(method: &str, target: &str, body: &str) {
+    let mut fields: Vec<String> = match serde_json::from_str::<HashMap<String, serde_json::Value>>(body) {
+        Ok(fields) => fields.into_keys().collect(),
+        Err(_) => parse_form(body).into_keys().collect(),
+    };
+    fields.sort();
+    println!("{} {} {} body fields: [{}]", "[REQUEST]".blue(), method, target, fields.join(", "));
+}
+
+fn parse_form(query: &str) -> HashMap<String, String> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(key, value)| {
+            let value = urlencoding::decode(&value.replace('+', " ")).map(|value| value.into_owned()).unwrap_or_default();
+            (key.to_string(), value)
+        })
+        .collect()
+}
@@ -0,0 +1,55 @@
+use clap::{Parser, Subcommand};
+
+#[derive(Parser, Debug)]
+#[command(
+    version,
+    about = "CLC miner",
+    after_help = "A challenge the network solved stops being mined as soon as the miner sees a new seed: on the next poll \
+(job_interval), when the daemon pushes one (job_source = \"sse\"), or, with polls slower than challenge_check_interval, \
+on the next challenge check. See clcminer.toml in the README."
+)]
+pub struct Cli {
+    /// Derive every private key (CPU threads and GPU base keys) from this seed
+    /// instead of the OS RNG, so runs against the same job are reproducible.
+    /// Keys mined this way are predictable, only use it for testing!
+    #[arg(long, global = true)]
+    pub deterministic_seed: Option<u64>,
+
+    /// Allow --deterministic-seed when mining against a daemon. Anyone who
+    /// knows the seed can derive the keys of the coins it mines
+    #[arg(long)]
+    pub allow_deterministic_on_network: bool,
+
+    /// Mine against a built-in challenge generator instead of a daemon. Solutions
+    /// are verified and counted locally and nothing is sent over the network
+    #[arg(long)]
+    pub local: bool,
+
+    /// Target for --local challenges as hex
+    #[arg(long, default_value = "0000ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff")]
+    pub local_diff: String,
+
+    /// Seconds between new --local challenges when nobody solves them
+    #[arg(long, default_value_t = 60)]
+    pub local_rotate: u64,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Measure CPU and GPU throughput offline against a synthetic job
+    Bench {
+        /// How long to mine for
+        #[arg(long, default_value_t = 30)]
+        seconds: u64,
+        /// Print the results as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Summarize the job journal: average job lifetime and our share of solved jobs
+    Jobs,
+    /// List the keys of found solutions whose submission outcome is unknown
+    Keys,
+}
@@ -6,17 +6,31 @@ use colored::*;
 struct CLCMinerConfigLoad {
     pub server: String,
     pub submit_server: Option<String>,
+    pub backup_servers: Option<Vec<String>>,
+    pub backup_submit_servers: Option<Vec<String>>,
     pub rewards_dir: String,
+    pub data_dir: Option<String>,
     pub thread: i64,
     pub gpu: Option<i64>,
     pub gpu_platform: Option<String>,
     pub gpu_workgroup_size: Option<usize>,
     pub gpu_batch_size: Option<usize>,
+    pub endomorphism: Option<bool>,
+    pub cpu_hasher: Option<String>,
+    pub cpu_affinity: Option<Vec<usize>>,
+    pub cpu_nice: Option<i32>,
+    pub cpu_sched_policy: Option<String>,
+    pub cpu_limit: Option<String>,
     #[serde(default)]
     pub on_mined: Option<String>,
     pub job_interval: Option<i64>,
+    pub job_source: Option<String>,
+    pub challenge_check_interval: Option<i64>,
+    pub job_retry_max: Option<i64>,
+    pub offline_grace: Option<i64>,
     pub report_interval: Option<i64>,
     pub pool_secret: Option<String>,
+    pub submit_method: Option<String>,
     pub reporting: Option<Reporting>,
 }
 
@@ -29,17 +43,31 @@ pub struct Reporting {
 pub struct CLCMinerConfig {
     pub server: String,
     pub rewards_dir: String,
+    pub data_dir: String,
     pub thread: i64,
     pub gpu: i64,
     pub gpu_platform: String,
     pub gpu_workgroup_size: usize,
     pub gpu_batch_size: usize,
+    pub endomorphism: bool,
+    pub cpu_hasher: String,
+    pub cpu_affinity: Vec<usize>,
+    pub cpu_nice: i32,
+    pub cpu_sched_policy: String,
+    pub cpu_limit: String,
     pub job_interval: i64,
+    pub job_source: String,
+    pub challenge_check_interval: i64,
+    pub job_retry_max: i64,
+    pub offline_grace: i64,
     pub report_interval: i64,
     pub on_mined: String,
     pub reporting: Reporting,
     pub pool_secret: String,
-    pub submit_server: String
+    pub submit_method: String,
+    pub submit_server: String,
+    pub backup_servers: Vec<String>,
+    pub backup_submit_servers: Vec<String>
 }
 
 impl CLCMinerConfig {
@@ -50,6 +78,20 @@ impl CLCMinerConfig {
     pub fn get_gpu_workgroup_size(&self) -> usize {
         self.gpu_workgroup_size
     }
+
+    /// Job servers in failover order, `server` first.
+    pub fn job_servers(&self) -> Vec<String> {
+        let mut servers = vec![self.server.clone()];
+        servers.extend(self.backup_servers.iter().cloned());
+        servers
+    }
+
+    /// Submit servers in failover order, `submit_server` first.
+    pub fn submit_servers(&self) -> Vec<String> {
+        let mut servers = vec![self.submit_server.clone()];
+        servers.extend(self.backup_submit_servers.iter().cloned());
+        servers
+    }
 }
 
 pub fn load() -> Result<CLCMinerConfig, String> {
@@ -74,6 +116,18 @@ pub fn load() -> Result<CLCMinerConfig, String> {
                         Some(submit_server) => submit_server.to_string(),
                         None => String::from("https://master.centrix.fi"),
                     };
+                    let backup_servers: Vec<String> = match &config.backup_servers {
+                        Some(servers) => servers.clone(),
+                        None => vec![],
+                    };
+                    let backup_submit_servers: Vec<String> = match &config.backup_submit_servers {
+                        Some(servers) => servers.clone(),
+                        None => vec![],
+                    };
+                    let data_dir: String = match &config.data_dir {
+                        Some(data_dir) => data_dir.to_string(),
+                        None => String::from("./data"),
+                    };
                     let on_mined: String = match &config.on_mined {
                         Some(on_mined) => on_mined.to_string(),
                         None => String::from(""),
@@ -82,6 +136,22 @@ pub fn load() -> Result<CLCMinerConfig, String> {
                         Some(job_interval) => *job_interval,
                         None => 1,
                     };
+                    let job_source: String = match &config.job_source {
+                        Some(job_source) => job_source.to_string(),
+                        None => String::from("poll"),
+                    };
+                    let challenge_check_interval: i64 = match &config.challenge_check_interval {
+                        Some(challenge_check_interval) => *challenge_check_interval,
+                        None => 1,
+                    };
+                    let job_retry_max: i64 = match &config.job_retry_max {
+                        Some(job_retry_max) => *job_retry_max,
+                        None => 60,
+                    };
+                    let offline_grace: i64 = match &config.offline_grace {
+                        Some(offline_grace) => *offline_grace,
+                        None => 120,
+                    };
                     let report_interval: i64 = match &config.report_interval {
                         Some(report_interval) => *report_interval,
                         None => 1,
@@ -90,6 +160,10 @@ pub fn load() -> Result<CLCMinerConfig, String> {
                         Some(pool_secret) => pool_secret.to_string(),
                         None => String::from(""),
                     };
+                    let submit_method: String = match &config.submit_method {
+                        Some(submit_method) => submit_method.to_string(),
+                        None => String::from("auto"),
+                    };
                     let gpu: i64 = match &config.gpu {
                         Some(gpu) => *gpu,
                         None => 0,
@@ -106,21 +180,59 @@ pub fn load() -> Result<CLCMinerConfig, String> {
                         Some(size) => *size,
                         None => 1048576, // 1M batch size for GPU
                     };
+                    let endomorphism: bool = match &config.endomorphism {
+                        Some(endomorphism) => *endomorphism,
+                        None => false,
+                    };
+                    let cpu_hasher: String = match &config.cpu_hasher {
+                        Some(cpu_hasher) => cpu_hasher.to_string(),
+                        None => String::from("auto"),
+                    };
+                    let cpu_affinity: Vec<usize> = match &config.cpu_affinity {
+                        Some(cores) => cores.clone(),
+                        None => vec![],
+                    };
+                    let cpu_nice: i32 = match &config.cpu_nice {
+                        Some(nice) => *nice,
+                        None => 0,
+                    };
+                    let cpu_sched_policy: String = match &config.cpu_sched_policy {
+                        Some(policy) => policy.to_string(),
+                        None => String::from("normal"),
+                    };
+                    let cpu_limit: String = match &config.cpu_limit {
+                        Some(limit) => limit.to_string(),
+                        None => String::from("100%"),
+                    };
 
                     return Ok(CLCMinerConfig {
                         server: config.server,
                         submit_server: submit_server,
+                        backup_servers,
+                        backup_submit_servers,
                         rewards_dir: config.rewards_dir,
+                        data_dir,
                         thread: config.thread,
                         gpu: gpu,
                         gpu_platform: gpu_platform,
                         gpu_workgroup_size: gpu_workgroup_size,
                         gpu_batch_size: gpu_batch_size,
+                        endomorphism,
+                        cpu_hasher,
+                        cpu_affinity,
+                        cpu_nice,
+                        cpu_sched_policy,
+                        cpu_limit,
                         job_interval: job_interval,
+                        job_source,
+                        challenge_check_interval,
+                        job_retry_max,
+                        offline_grace,
                         report_interval: report_interval,
                         on_mined: on_mined,
                         reporting: reporting,
-                        pool_secret: pool_secret
+                        pool_secret: pool_secret,
+                        submit_method
                     });
                 },
                 Err(e) => {
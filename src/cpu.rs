@@ -0,0 +1,185 @@
+use std::sync::Arc;
+use std::time::Instant;
+
+use secp256k1::{PublicKey, Secp256k1, SecretKey};
+use colored::*;
+use tokio::runtime::Handle;
+use tokio::sync::{mpsc, watch};
+
+use crate::endomorphism::{Endomorphism, Variant};
+use crate::get_job::JobSnapshot;
+use crate::hash256::Hash256;
+use crate::hasher::{Backend, BatchHasher, PUBKEY_LEN};
+use crate::rng::key_rng;
+use crate::sched::ThreadPolicy;
+use crate::stats::MinerStats;
+
+// Candidates a worker hashes before publishing them to its stats shard
+const STATS_BATCH: u64 = 1024;
+// Keypairs generated per hashing batch, a multiple of every backend's lane count
+const KEYS_PER_BATCH: usize = 16;
+
+#[derive(Debug, Clone, Copy)]
+pub struct CpuMinerOptions {
+    pub endomorphism: bool,
+    pub backend: Backend,
+    pub deterministic_seed: Option<u64>,
+}
+
+pub enum CpuEvent {
+    Solution {
+        private_key: SecretKey,
+        public_key: PublicKey,
+        hash: Hash256,
+        job: Arc<JobSnapshot>,
+    },
+}
+
+pub struct CpuMiningPool {
+    pool: rayon::ThreadPool,
+    policy: ThreadPolicy,
+}
+
+impl CpuMiningPool {
+    pub fn new(threads: usize, policy: ThreadPolicy) -> Result<Self, rayon::ThreadPoolBuildError> {
+        let start_policy = policy.clone();
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .thread_name(|i| format!("cpu-miner-{}", i))
+            .start_handler(move |i| {
+                if let Err(e) = start_policy.apply(i) {
+                    println!("{} Failed to apply thread policy to CPU thread {}: {}", "[WARN]".yellow(), i, e);
+                }
+            })
+            .build()?;
+        Ok(CpuMiningPool { pool, policy })
+    }
+
+    /// Runs one mining loop on every thread of the pool. Workers never touch
+    /// the async runtime's locks: they read the job from `job_rx`, count into
+    /// their own `stats` shard and send solutions through `events`.
+    pub fn start(
+        &self,
+        job_rx: watch::Receiver<Arc<JobSnapshot>>,
+        options: CpuMinerOptions,
+        stats: Arc<MinerStats>,
+        events: mpsc::UnboundedSender<CpuEvent>,
+    ) {
+        let runtime = Handle::current();
+        let policy = self.policy.clone();
+        self.pool.spawn_broadcast(move |ctx| {
+            mine(&runtime, job_rx.clone(), options, &policy, &stats, ctx.index(), events.clone());
+        });
+    }
+}
+
+fn mine(
+    runtime: &Handle,
+    mut job_rx: watch::Receiver<Arc<JobSnapshot>>,
+    options: CpuMinerOptions,
+    policy: &ThreadPolicy,
+    stats: &MinerStats,
+    shard: usize,
+    events: mpsc::UnboundedSender<CpuEvent>,
+) {
+    let secp = Secp256k1::new();
+    let endomorphism = if options.endomorphism { Some(Endomorphism::new()) } else { None };
+    let mut hasher = BatchHasher::new(options.backend);
+    let mut rng = key_rng(options.deterministic_seed, shard as u64);
+    // Reused between batches: keypairs, then (keypair index, variant) and pubkey per candidate
+    let mut keypairs: Vec<(SecretKey, PublicKey)> = Vec::with_capacity(KEYS_PER_BATCH);
+    let mut candidates: Vec<(usize, Variant)> = Vec::new();
+    let mut pubkeys: Vec<[u8; PUBKEY_LEN]> = Vec::new();
+    let mut hash_out: Vec<[u8; 32]> = Vec::new();
+    let mut snapshot = job_rx.borrow_and_update().clone();
+    let mut hashes: u64 = 0;
+    // Solutions the hashes since the last flush were worth, for luck
+    let mut expected = 0.0;
+    let mut best = Hash256::MAX;
+
+    loop {
+        match job_rx.has_changed() {
+            Ok(true) => snapshot = job_rx.borrow_and_update().clone(),
+            Ok(false) => {}
+            Err(_) => return, // Job task is gone
+        }
+        if !snapshot.is_active() {
+            // Sleep until the job task publishes something new
+            if runtime.block_on(job_rx.changed()).is_err() {
+                return;
+            }
+            snapshot = job_rx.borrow_and_update().clone();
+            continue;
+        }
+        let job = &snapshot.job;
+
+        hasher.set_seed(&job.seed);
+        let batch_start = Instant::now();
+
+        keypairs.clear();
+        candidates.clear();
+        pubkeys.clear();
+        for _ in 0..KEYS_PER_BATCH {
+            let (secret_key, public_key) = secp.generate_keypair(&mut rng);
+            match &endomorphism {
+                Some(endo) => {
+                    for (variant, candidate) in endo.candidates(&public_key) {
+                        candidates.push((keypairs.len(), variant));
+                        pubkeys.push(candidate);
+                    }
+                }
+                None => {
+                    candidates.push((keypairs.len(), Variant::IDENTITY));
+                    pubkeys.push(public_key.serialize_uncompressed());
+                }
+            }
+            keypairs.push((secret_key, public_key));
+        }
+        hash_out.resize(pubkeys.len(), [0u8; 32]);
+        hasher.hash(&pubkeys, &mut hash_out);
+        hashes += pubkeys.len() as u64;
+        expected += pubkeys.len() as f64 * job.diff.probability();
+
+        for (i, hash_bytes) in hash_out.iter().enumerate() {
+            // The difficulty of the key we just created and hashed
+            let hash = Hash256(*hash_bytes);
+            if hash < best {
+                best = hash;
+            }
+            if hash.meets(&job.diff) {
+                let (key_index, variant) = candidates[i];
+                let (secret_key, public_key) = keypairs[key_index];
+                let (private_key, public_key) = match &endomorphism {
+                    Some(endo) => match endo.recover_keypair(&secp, &secret_key, variant, &pubkeys[i]) {
+                        Ok(keypair) => keypair,
+                        Err(e) => {
+                            println!("\n{} Endomorphism key recovery failed: {}", "[ERROR]".red(), e);
+                            continue;
+                        }
+                    },
+                    None => (secret_key, public_key),
+                };
+                let solution = CpuEvent::Solution {
+                    private_key,
+                    public_key,
+                    hash,
+                    job: Arc::clone(&snapshot),
+                };
+                if events.send(solution).is_err() {
+                    return;
+                }
+                break;
+            }
+        }
+
+        if hashes >= STATS_BATCH {
+            stats.add_hashes(shard, hashes);
+            stats.add_expected(expected);
+            stats.submit_best(&best);
+            hashes = 0;
+            expected = 0.0;
+            best = Hash256::MAX;
+        }
+        policy.throttle(batch_start.elapsed());
+    }
+}
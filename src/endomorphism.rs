@@ -0,0 +1,172 @@
+use num_bigint::BigUint;
+use secp256k1::{All, PublicKey, Scalar, Secp256k1, SecretKey};
+
+// secp256k1 field prime p
+const FIELD_PRIME: &str = "FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFEFFFFFC2F";
+// Cube root of unity mod p, (beta * x, y) == lambda * (x, y)
+const BETA: &str = "7AE96A2B657C07106E64479EAC3434E99CF0497512F58995C1396C28719501EE";
+// Matching cube root of unity mod the group order n
+const LAMBDA: [u8; 32] = [
+    0x53, 0x63, 0xad, 0x4c, 0xc0, 0x5c, 0x30, 0xe0, 0xa5, 0x26, 0x1c, 0x02, 0x88, 0x12, 0x64, 0x5a,
+    0x12, 0x2e, 0x22, 0xea, 0x20, 0x81, 0x66, 0x78, 0xdf, 0x02, 0x96, 0x7c, 0x1b, 0x23, 0xbd, 0x72,
+];
+
+pub const CANDIDATES_PER_POINT: usize = 6;
+
+/// Which of the six related points a candidate pubkey is.
+/// `rotation` is how many times the endomorphism was applied (lambda^rotation * k),
+/// `negated` means the point was negated afterwards (n - k).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Variant {
+    pub rotation: u8,
+    pub negated: bool,
+}
+
+impl Variant {
+    pub const IDENTITY: Variant = Variant { rotation: 0, negated: false };
+}
+
+pub struct Endomorphism {
+    p: BigUint,
+    beta: BigUint,
+    beta_squared: BigUint,
+    lambda: Scalar,
+}
+
+impl Endomorphism {
+    pub fn new() -> Self {
+        let p = BigUint::parse_bytes(FIELD_PRIME.as_bytes(), 16).unwrap();
+        let beta = BigUint::parse_bytes(BETA.as_bytes(), 16).unwrap();
+        let beta_squared = (&beta * &beta) % &p;
+        Endomorphism {
+            p,
+            beta,
+            beta_squared,
+            lambda: Scalar::from_be_bytes(LAMBDA).unwrap(),
+        }
+    }
+
+    /// Returns the uncompressed serializations of P, -P, lambda*P, -lambda*P,
+    /// lambda^2*P and -lambda^2*P. Only field multiplications are needed, no
+    /// scalar multiplication.
+    pub fn candidates(&self, public_key: &PublicKey) -> [(Variant, [u8; 65]); CANDIDATES_PER_POINT] {
+        let serialized = public_key.serialize_uncompressed();
+        let x = BigUint::from_bytes_be(&serialized[1..33]);
+        let y = BigUint::from_bytes_be(&serialized[33..65]);
+        let neg_y = &self.p - &y;
+
+        let xs = [x.clone(), (&x * &self.beta) % &self.p, (&x * &self.beta_squared) % &self.p];
+        let mut out = [(Variant::IDENTITY, [0u8; 65]); CANDIDATES_PER_POINT];
+        for (rotation, x) in xs.iter().enumerate() {
+            for (i, (negated, y)) in [(false, &y), (true, &neg_y)].into_iter().enumerate() {
+                let entry = &mut out[rotation * 2 + i];
+                entry.0 = Variant { rotation: rotation as u8, negated };
+                entry.1[0] = 0x04;
+                write_be_32(x, &mut entry.1[1..33]);
+                write_be_32(y, &mut entry.1[33..65]);
+            }
+        }
+        out
+    }
+
+    /// Private key for the given variant of the point generated by `secret_key`.
+    pub fn recover_secret(&self, secret_key: &SecretKey, variant: Variant) -> SecretKey {
+        let mut key = *secret_key;
+        for _ in 0..variant.rotation {
+            // lambda is invertible mod n, so the product can never be zero
+            key = key.mul_tweak(&self.lambda).expect("lambda * k is never zero");
+        }
+        if variant.negated {
+            key = key.negate();
+        }
+        key
+    }
+
+    /// Reconstructs the keypair for a candidate that hit and checks that the
+    /// private key really derives the candidate pubkey before it gets submitted.
+    pub fn recover_keypair(
+        &self,
+        secp: &Secp256k1<All>,
+        secret_key: &SecretKey,
+        variant: Variant,
+        candidate: &[u8; 65],
+    ) -> Result<(SecretKey, PublicKey), String> {
+        let private_key = self.recover_secret(secret_key, variant);
+        let public_key = PublicKey::from_slice(candidate).map_err(|e| format!("invalid candidate pubkey: {}", e))?;
+        if PublicKey::from_secret_key(secp, &private_key) != public_key {
+            return Err(format!("reconstructed key does not match pubkey for variant {:?}", variant));
+        }
+        Ok((private_key, public_key))
+    }
+}
+
+fn write_be_32(value: &BigUint, out: &mut [u8]) {
+    let bytes = value.to_bytes_be();
+    out.fill(0);
+    out[32 - bytes.len()..].copy_from_slice(&bytes);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use secp256k1::rand::rngs::OsRng;
+
+    // lambda * G, worked out independently: x is beta * Gx mod p, y is Gy
+    const LAMBDA_G: &str = "04\
+        bcace2e99da01887ab0102b696902325872844067f15e98da7bba04400b88fcb\
+        483ada7726a3c4655da4fbfc0e1108a8fd17b448a68554199c47d08ffb10d4b8";
+
+    #[test]
+    fn every_variant_recovers_its_key() {
+        let secp = Secp256k1::new();
+        let endo = Endomorphism::new();
+        for _ in 0..200 {
+            let (secret_key, public_key) = secp.generate_keypair(&mut OsRng);
+            for (variant, candidate) in endo.candidates(&public_key) {
+                let recovered = endo.recover_secret(&secret_key, variant);
+                assert_eq!(
+                    PublicKey::from_secret_key(&secp, &recovered).serialize_uncompressed(),
+                    candidate,
+                    "variant {:?} of {}",
+                    variant,
+                    public_key
+                );
+                assert!(endo.recover_keypair(&secp, &secret_key, variant, &candidate).is_ok());
+            }
+        }
+    }
+
+    #[test]
+    fn candidates_cover_all_six_variants() {
+        let secp = Secp256k1::new();
+        let (_, public_key) = secp.generate_keypair(&mut OsRng);
+        let candidates = Endomorphism::new().candidates(&public_key);
+        assert_eq!(candidates[0], (Variant::IDENTITY, public_key.serialize_uncompressed()));
+        for rotation in 0..3 {
+            for negated in [false, true] {
+                assert_eq!(candidates.iter().filter(|(variant, _)| *variant == Variant { rotation, negated }).count(), 1);
+            }
+        }
+    }
+
+    #[test]
+    fn lambda_and_beta_agree() {
+        let secp = Secp256k1::new();
+        let endo = Endomorphism::new();
+        let n = BigUint::parse_bytes(b"FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFEBAAEDCE6AF48A03BBFD25E8CD0364141", 16).unwrap();
+        let lambda = BigUint::from_bytes_be(&LAMBDA);
+        // Both are nontrivial cube roots of unity
+        assert_eq!(lambda.modpow(&BigUint::from(3u8), &n), BigUint::from(1u8));
+        assert_eq!(endo.beta.modpow(&BigUint::from(3u8), &endo.p), BigUint::from(1u8));
+        assert_eq!(endo.beta_squared, (&endo.beta * &endo.beta) % &endo.p);
+
+        let mut one = [0u8; 32];
+        one[31] = 1;
+        let generator = PublicKey::from_secret_key(&secp, &SecretKey::from_byte_array(&one).unwrap());
+        let lambda_g = PublicKey::from_secret_key(&secp, &SecretKey::from_byte_array(&LAMBDA).unwrap());
+        assert_eq!(hex::encode(lambda_g.serialize_uncompressed()), LAMBDA_G);
+        let rotated = Variant { rotation: 1, negated: false };
+        let candidates = endo.candidates(&generator);
+        assert_eq!(candidates.iter().find(|(variant, _)| *variant == rotated).unwrap().1, lambda_g.serialize_uncompressed());
+    }
+}
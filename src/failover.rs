@@ -0,0 +1,154 @@
+use colored::*;
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::backoff::Backoff;
+
+struct Server {
+    url: String,
+    // Not tried before this unless every other server is down too
+    down_until: Option<Instant>,
+    backoff: Backoff,
+}
+
+/// Ordered list of daemons for one purpose (jobs or submissions), the first
+/// entry is the primary. Failed servers are marked down for a growing cool
+/// down, so the primary is retried and taken back once it recovers.
+pub struct ServerList {
+    name: &'static str,
+    servers: Mutex<Vec<Server>>,
+    // Index of the server that answered last
+    in_use: Mutex<usize>,
+}
+
+impl ServerList {
+    pub fn new(name: &'static str, urls: Vec<String>) -> Self {
+        ServerList::with_cool_down(name, urls, Duration::from_secs(5), Duration::from_secs(300))
+    }
+
+    fn with_cool_down(name: &'static str, urls: Vec<String>, initial: Duration, max: Duration) -> Self {
+        let servers = urls
+            .into_iter()
+            .map(|url| Server {
+                url,
+                down_until: None,
+                backoff: Backoff::new(initial, max),
+            })
+            .collect();
+        ServerList { name, servers: Mutex::new(servers), in_use: Mutex::new(0) }
+    }
+
+    /// Servers in the order they should be tried: healthy ones by priority,
+    /// then the ones currently marked down.
+    pub fn candidates(&self) -> Vec<(usize, String)> {
+        let now = Instant::now();
+        let servers = self.servers.lock().unwrap();
+        let (mut healthy, down): (Vec<_>, Vec<_>) = servers
+            .iter()
+            .enumerate()
+            .map(|(index, server)| (index, server.url.clone(), server.down_until.is_none_or(|until| now >= until)))
+            .partition(|(_, _, healthy)| *healthy);
+        healthy.extend(down);
+        healthy.into_iter().map(|(index, url, _)| (index, url)).collect()
+    }
+
+    pub fn report_success(&self, index: usize) {
+        let url = {
+            let mut servers = self.servers.lock().unwrap();
+            let server = &mut servers[index];
+            server.down_until = None;
+            server.backoff.reset();
+            server.url.clone()
+        };
+        let mut in_use = self.in_use.lock().unwrap();
+        if *in_use != index {
+            if index == 0 {
+                println!("\n{} Primary {} server {} is back, switching to it", "[INFO]".blue(), self.name, url);
+            } else {
+                println!("\n{} Failing over to {} server {}", "[WARN]".yellow(), self.name, url);
+            }
+            *in_use = index;
+        }
+    }
+
+    pub fn report_failure(&self, index: usize) {
+        let mut servers = self.servers.lock().unwrap();
+        let server = &mut servers[index];
+        let cool_down = server.backoff.next_delay();
+        // Only logged once per outage, retries while it stays down are quiet
+        if server.down_until.is_none() {
+            println!("\n{} {} server {} is down", "[WARN]".yellow(), self.name, server.url);
+        }
+        server.down_until = Some(Instant::now() + cool_down);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list(cool_down: Duration) -> ServerList {
+        let urls = ["http://primary", "http://backup1", "http://backup2"].map(String::from).to_vec();
+        ServerList::with_cool_down("job", urls, cool_down, cool_down * 4)
+    }
+
+    fn order(list: &ServerList) -> Vec<usize> {
+        list.candidates().into_iter().map(|(index, _)| index).collect()
+    }
+
+    fn remaining_cool_down(list: &ServerList, index: usize) -> Duration {
+        let until = list.servers.lock().unwrap()[index].down_until.unwrap();
+        until.saturating_duration_since(Instant::now())
+    }
+
+    #[test]
+    fn primary_first_then_backups() {
+        let list = list(Duration::from_secs(60));
+        assert_eq!(list.candidates()[0], (0, String::from("http://primary")));
+        assert_eq!(order(&list), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn down_servers_go_last_in_priority_order() {
+        let list = list(Duration::from_secs(60));
+        list.report_failure(0);
+        assert_eq!(order(&list), vec![1, 2, 0]);
+        list.report_failure(1);
+        assert_eq!(order(&list), vec![2, 0, 1]);
+        // Still tried when everything is down
+        list.report_failure(2);
+        assert_eq!(order(&list), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn cool_down_grows_with_failures_and_resets_on_success() {
+        let list = list(Duration::from_secs(60));
+        list.report_failure(0);
+        assert!(remaining_cool_down(&list, 0) <= Duration::from_secs(60));
+        list.report_failure(0);
+        // The second delay is drawn from the upper half of 120s
+        assert!(remaining_cool_down(&list, 0) > Duration::from_secs(59));
+
+        list.report_success(0);
+        assert!(list.servers.lock().unwrap()[0].down_until.is_none());
+        list.report_failure(0);
+        assert!(remaining_cool_down(&list, 0) <= Duration::from_secs(60));
+    }
+
+    #[test]
+    fn primary_is_taken_back_once_it_recovers() {
+        let list = list(Duration::from_millis(20));
+        list.report_failure(0);
+        list.report_success(1);
+        assert_eq!(*list.in_use.lock().unwrap(), 1);
+        assert_eq!(order(&list), vec![1, 2, 0]);
+
+        // Tried first again once its cool down is over, and used when it answers
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(order(&list), vec![0, 1, 2]);
+        list.report_success(0);
+        assert_eq!(*list.in_use.lock().unwrap(), 0);
+        assert_eq!(order(&list), vec![0, 1, 2]);
+    }
+}
@@ -1,5 +1,13 @@
-use num_bigint::BigUint;
 use serde::Deserialize;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use reqwest::{Client, StatusCode};
+use tokio::sync::watch;
+
+use crate::failover::ServerList;
+use crate::hash256::Target;
+use crate::job_timing::ClockSample;
 
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -10,40 +18,329 @@ pub struct Body {
     pub last_found: u64,
 }
 
+// Far above any real challenge reward, anything larger is a broken daemon
+const MAX_REWARD: f64 = 1_000_000.0;
+
 #[derive(Debug, Clone)]
 pub struct Job {
     pub seed: String,
-    pub diff: BigUint,
+    pub diff: Target,
     pub reward: f64,
+    // Daemon time in Unix milliseconds, see JobClock before comparing with ours
     pub last_found: u64,
+    // The daemon's clock reading from the response that carried the job
+    pub clock: Option<ClockSample>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobState {
+    // No job received from the server yet
+    Waiting,
+    // We found a solution for this job, miners should idle until the next one
+    Paused,
+    // The job server has been unreachable for longer than the grace period
+    Disconnected,
+    Active,
+}
+
+/// Immutable view of the current job shared with all miners. `epoch` grows
+/// every time a job is published, so results can be tagged with the job they
+/// were computed for.
+#[derive(Debug)]
+pub struct JobSnapshot {
+    pub epoch: u64,
+    pub state: JobState,
+    pub job: Job,
+}
+
+impl JobSnapshot {
+    pub fn is_active(&self) -> bool {
+        self.state == JobState::Active
+    }
+}
+
+pub struct JobPublisher {
+    tx: watch::Sender<Arc<JobSnapshot>>,
+}
+
+impl JobPublisher {
+    pub fn new() -> (JobPublisher, watch::Receiver<Arc<JobSnapshot>>) {
+        let waiting = JobSnapshot {
+            epoch: 0,
+            state: JobState::Waiting,
+            job: Job { seed: String::new(), diff: Target::ZERO, reward: 0.0, last_found: 0, clock: None },
+        };
+        let (tx, rx) = watch::channel(Arc::new(waiting));
+        (JobPublisher { tx }, rx)
+    }
+
+    pub fn current(&self) -> Arc<JobSnapshot> {
+        self.tx.borrow().clone()
+    }
+
+    /// Publishes `job` as active under a new epoch and returns the snapshot.
+    pub fn publish(&self, job: Job) -> Arc<JobSnapshot> {
+        let mut published = None;
+        self.tx.send_modify(|current| {
+            let snapshot = Arc::new(JobSnapshot { epoch: current.epoch + 1, state: JobState::Active, job });
+            published = Some(Arc::clone(&snapshot));
+            *current = snapshot;
+        });
+        published.unwrap()
+    }
+
+    /// Pauses mining, but only if `epoch` is still the current job. A late
+    /// result for an already replaced job must not stop work on the new one.
+    pub fn pause(&self, epoch: u64) -> bool {
+        self.tx.send_if_modified(|current| {
+            if current.epoch != epoch || current.state != JobState::Active {
+                return false;
+            }
+            *current = Arc::new(JobSnapshot { epoch, state: JobState::Paused, job: current.job.clone() });
+            true
+        })
+    }
+
+    /// Undoes `pause` when our solution for `epoch` didn't close the challenge,
+    /// so mining doesn't wait for a job the daemon has no reason to send.
+    pub fn resume(&self, epoch: u64) -> bool {
+        self.tx.send_if_modified(|current| {
+            if current.epoch != epoch || current.state != JobState::Paused {
+                return false;
+            }
+            *current = Arc::new(JobSnapshot { epoch, state: JobState::Active, job: current.job.clone() });
+            true
+        })
+    }
+
+    /// Stops mining on the current job because the daemon can't be reached.
+    /// The next successful fetch publishes the job again under a new epoch.
+    pub fn disconnect(&self) -> bool {
+        self.tx.send_if_modified(|current| {
+            if current.state != JobState::Active {
+                return false;
+            }
+            *current = Arc::new(JobSnapshot { epoch: current.epoch, state: JobState::Disconnected, job: current.job.clone() });
+            true
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum JobError {
+    Request(reqwest::Error),
+    HttpStatus(StatusCode),
+    // The push stream broke or went quiet
+    Stream(String),
+    BadJson(serde_json::Error),
+    InvalidDiff(String),
+    EmptySeed,
+    AbsurdReward(f64),
+}
+
+impl JobError {
+    /// The daemon answered, but with a job we can't mine. Retrying the
+    /// connection won't help, so these are logged and skipped.
+    pub fn is_invalid_job(&self) -> bool {
+        matches!(self, JobError::BadJson(_) | JobError::InvalidDiff(_) | JobError::EmptySeed | JobError::AbsurdReward(_))
+    }
 }
 
-impl Job {
-    pub fn get_wait_job() -> Job {
-        // Returns empty job with seed ="wait" ment to make miner threads wait until job is set
-        Job { seed: String::from("wait"), diff: BigUint::from(0_u32), reward: 0.0, last_found: 0 }
+impl fmt::Display for JobError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JobError::Request(e) => write!(f, "request failed: {}", e),
+            JobError::HttpStatus(status) => write!(f, "daemon answered {}", status),
+            JobError::Stream(e) => write!(f, "{}", e),
+            JobError::BadJson(e) => write!(f, "malformed job JSON: {}", e),
+            JobError::InvalidDiff(e) => write!(f, "invalid diff: {}", e),
+            JobError::EmptySeed => write!(f, "job has an empty seed"),
+            JobError::AbsurdReward(reward) => write!(f, "job has an absurd reward of {}", reward),
+        }
     }
-    
-    pub fn get_pause_job(&self) -> Job {
-        // Returns empty job with seed ="wait" ment to make miner threads wait until job is set
-        Job { seed: String::from("wait"), diff: self.diff.clone(), reward: self.reward.clone(), last_found: self.last_found.clone() }
+}
+
+impl std::error::Error for JobError {}
+
+impl From<reqwest::Error> for JobError {
+    fn from(e: reqwest::Error) -> Self {
+        JobError::Request(e)
     }
 }
 
-impl From<Body> for Job {
-    fn from(body: Body) -> Self {
-        Job {
+impl TryFrom<Body> for Job {
+    type Error = JobError;
+
+    fn try_from(body: Body) -> Result<Self, Self::Error> {
+        if body.seed.is_empty() {
+            return Err(JobError::EmptySeed);
+        }
+        if !(body.reward.is_finite() && body.reward >= 0.0 && body.reward <= MAX_REWARD) {
+            return Err(JobError::AbsurdReward(body.reward));
+        }
+        Ok(Job {
             seed: body.seed,
-            diff: BigUint::parse_bytes(body.diff.as_bytes(), 16).unwrap(),
+            diff: Target::from_hex(&body.diff).map_err(JobError::InvalidDiff)?,
             reward: body.reward,
             last_found: body.last_found,
+            clock: None,
+        })
+    }
+}
+
+/// Parses a job as the daemon sends it, from `/get-challenge` or the push stream.
+pub fn parse_job(json: &str) -> Result<Job, JobError> {
+    let body: Body = serde_json::from_str(json).map_err(JobError::BadJson)?;
+    Job::try_from(body)
+}
+
+pub async fn get_job(server: String) -> Result<Job, JobError> {
+    let client = Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()?;
+    let sent = SystemTime::now();
+    let response = client.get(format!("{}/get-challenge", server)).send().await?;
+    if !response.status().is_success() {
+        return Err(JobError::HttpStatus(response.status()));
+    }
+    let clock = ClockSample::from_headers(response.headers(), sent, SystemTime::now());
+    let text = response.text().await?;
+
+    let mut job = parse_job(&text)?;
+    job.clock = clock;
+    Ok(job)
+}
+
+/// Asks each job server in failover order until one hands out a job. A
+/// server handing out broken jobs counts as down just like an unreachable one.
+pub async fn get_job_failover(servers: &ServerList) -> Result<Job, JobError> {
+    let mut last_error = None;
+    for (index, server) in servers.candidates() {
+        match get_job(server).await {
+            Ok(job) => {
+                servers.report_success(index);
+                return Ok(job);
+            }
+            Err(e) => {
+                last_error = Some(e);
+                servers.report_failure(index);
+            }
         }
     }
+    // The primary server is always configured, so something was tried
+    Err(last_error.unwrap())
 }
 
-pub async fn get_job(server: String) -> Result<Job, reqwest::Error> {
-    let response = reqwest::get(format!("{}/get-challenge", server)).await?;
-    let body: Body = response.json().await?;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    Ok(Job::from(body))
+    const DIFF: &str = "00000000ffffffffffffffffffffffffffffffffffffffffffffffffffffffff";
+
+    fn body(seed: &str, diff: &str, reward: f64) -> Body {
+        Body { seed: seed.to_string(), diff: diff.to_string(), reward, last_found: 0 }
+    }
+
+    #[test]
+    fn valid_job_parses() {
+        let json = format!(r#"{{"seed":"abc","diff":"{}","reward":2.5,"lastFound":1700000000000}}"#, DIFF);
+        let job = parse_job(&json).unwrap();
+        assert_eq!(job.seed, "abc");
+        assert_eq!(job.diff, Target::from_hex(DIFF).unwrap());
+        assert_eq!(job.reward, 2.5);
+        assert_eq!(job.last_found, 1_700_000_000_000);
+        assert!(job.clock.is_none());
+    }
+
+    #[test]
+    fn malformed_json_is_bad_json() {
+        for json in ["", "not json", r#"{"seed":"abc"}"#, r#"{"seed":"abc","diff":"ff","reward":"1","lastFound":0}"#] {
+            let error = parse_job(json).unwrap_err();
+            assert!(matches!(error, JobError::BadJson(_)), "{:?} gave {:?}", json, error);
+            assert!(error.is_invalid_job());
+        }
+    }
+
+    #[test]
+    fn invalid_diff_is_rejected() {
+        let error = Job::try_from(body("abc", "xyz", 1.0)).unwrap_err();
+        assert!(matches!(error, JobError::InvalidDiff(_)), "{:?}", error);
+        assert!(error.is_invalid_job());
+    }
+
+    #[test]
+    fn empty_seed_is_rejected() {
+        let error = Job::try_from(body("", DIFF, 1.0)).unwrap_err();
+        assert!(matches!(error, JobError::EmptySeed), "{:?}", error);
+        assert!(error.is_invalid_job());
+    }
+
+    #[test]
+    fn absurd_rewards_are_rejected() {
+        for reward in [f64::NAN, f64::INFINITY, -1.0, MAX_REWARD * 2.0] {
+            let error = Job::try_from(body("abc", DIFF, reward)).unwrap_err();
+            assert!(matches!(error, JobError::AbsurdReward(_)), "{} gave {:?}", reward, error);
+            assert!(error.is_invalid_job());
+        }
+        assert!(Job::try_from(body("abc", DIFF, 0.0)).is_ok());
+        assert!(Job::try_from(body("abc", DIFF, MAX_REWARD)).is_ok());
+    }
+
+    fn job(seed: &str) -> Job {
+        Job::try_from(body(seed, DIFF, 1.0)).unwrap()
+    }
+
+    #[test]
+    fn pause_ignores_stale_epochs() {
+        let (publisher, rx) = JobPublisher::new();
+        let old = publisher.publish(job("old"));
+        let new = publisher.publish(job("new"));
+        assert!(!publisher.pause(old.epoch));
+        assert_eq!(rx.borrow().state, JobState::Active);
+        assert_eq!(rx.borrow().epoch, new.epoch);
+
+        assert!(publisher.pause(new.epoch));
+        assert_eq!(rx.borrow().state, JobState::Paused);
+        assert_eq!(rx.borrow().epoch, new.epoch);
+        // Already paused
+        assert!(!publisher.pause(new.epoch));
+    }
+
+    #[test]
+    fn resume_only_leaves_a_matching_pause() {
+        let (publisher, rx) = JobPublisher::new();
+        // Nothing published yet, Waiting stays Waiting
+        assert!(!publisher.resume(0));
+        assert_eq!(rx.borrow().state, JobState::Waiting);
+
+        let snapshot = publisher.publish(job("abc"));
+        // Not paused
+        assert!(!publisher.resume(snapshot.epoch));
+        assert!(publisher.pause(snapshot.epoch));
+        assert!(!publisher.resume(snapshot.epoch + 1));
+        assert_eq!(rx.borrow().state, JobState::Paused);
+        assert!(publisher.resume(snapshot.epoch));
+        assert_eq!(rx.borrow().state, JobState::Active);
+        assert_eq!(rx.borrow().epoch, snapshot.epoch);
+
+        assert!(publisher.disconnect());
+        assert!(!publisher.resume(snapshot.epoch));
+        assert_eq!(rx.borrow().state, JobState::Disconnected);
+    }
+
+    #[test]
+    fn publish_after_disconnect_starts_a_new_epoch() {
+        let (publisher, rx) = JobPublisher::new();
+        let before = publisher.publish(job("abc"));
+        assert!(publisher.disconnect());
+        assert!(!publisher.disconnect());
+        assert_eq!(rx.borrow().state, JobState::Disconnected);
+        assert_eq!(rx.borrow().epoch, before.epoch);
+
+        let after = publisher.publish(job("abc"));
+        assert_eq!(after.epoch, before.epoch + 1);
+        assert_eq!(after.state, JobState::Active);
+        assert_eq!(rx.borrow().epoch, after.epoch);
+        assert!(rx.borrow().is_active());
+    }
 }
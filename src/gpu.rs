@@ -4,7 +4,8 @@ use colored::*;
 use secp256k1::{PublicKey, SecretKey, Secp256k1};
 use secp256k1::hashes::{sha256, Hash};
 use hex::encode;
-use num_bigint::BigUint;
+
+use crate::hash256::{Hash256, Target};
 
 const GPU_BATCH_SIZE: usize = 1024;
 
@@ -336,19 +337,16 @@ impl GPUMiner {
     
     pub async fn mine_batch(
         &mut self,
-        target_diff: &BigUint,
+        target_diff: &Target,
         seed: &str,
         base_nonce: u64
-    ) -> Result<Option<(SecretKey, PublicKey, String)>, Box<dyn std::error::Error + Send + Sync>> {
+    ) -> Result<Option<(SecretKey, PublicKey, Hash256)>, Box<dyn std::error::Error + Send + Sync>> {
         // Prepare seed data
         let seed_bytes = seed.as_bytes();
         let seed_len = seed_bytes.len().min(64);
         
-        // Prepare target data (simplified - just use first 32 bytes)
-        let target_bytes = target_diff.to_bytes_be();
-        let mut target_array = vec![0u8; 32];
-        let copy_len = target_bytes.len().min(32);
-        target_array[32 - copy_len..].copy_from_slice(&target_bytes[..copy_len]);
+        // Target is already big-endian and 32 bytes wide
+        let target_array = target_diff.0.to_vec();
         
         // Prepare nonce data (64-bit split into two 32-bit values)
         let nonce_low = (base_nonce & 0xFFFFFFFF) as u32;
@@ -413,7 +411,7 @@ impl GPUMiner {
             let combined = format!("{}{}", pub_key_hex, seed);
             let hashed = sha256::Hash::hash(combined.as_bytes());
             
-            return Ok(Some((secret_key, public_key, hashed.to_string())));
+            return Ok(Some((secret_key, public_key, Hash256(hashed.to_byte_array()))));
         }
         
         Ok(None)
@@ -529,13 +527,13 @@ impl GPUMiningPool {
     
     pub async fn mine_parallel(
         &mut self,
-        target_diff: &BigUint,
+        target_diff: &Target,
         seed: &str,
         base_nonce: u64
-    ) -> Result<Option<(SecretKey, PublicKey, String)>, Box<dyn std::error::Error + Send + Sync>> {
+    ) -> Result<Option<(SecretKey, PublicKey, Hash256)>, Box<dyn std::error::Error + Send + Sync>> {
         // Only use active miners for processing
         for (i, miner) in self.miners.iter_mut().take(self.active_miners).enumerate() {
-            let target_diff = target_diff.clone();
+            let target_diff = *target_diff;
             let seed = seed.to_string();
             let nonce = base_nonce + (i as u64 * GPU_BATCH_SIZE as u64);
             
@@ -562,6 +560,11 @@ impl GPUMiningPool {
     pub fn get_active_miners(&self) -> usize {
         self.active_miners
     }
+
+    /// Keys hashed by one `mine_parallel` call, a kernel batch per active miner.
+    pub fn keys_per_call(&self) -> usize {
+        self.active_miners * GPU_BATCH_SIZE
+    }
     
     pub fn get_total_compute_units(&self) -> u32 {
         self.miners.iter()
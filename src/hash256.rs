@@ -0,0 +1,187 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// A sha256(pubkey_hex + seed) result. Stored big-endian, so the derived
+/// ordering is the numeric ordering the daemon compares with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Hash256(pub [u8; 32]);
+
+/// A job difficulty, a hash solves the job when it is at or below the target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Target(pub [u8; 32]);
+
+impl Hash256 {
+    pub const MAX: Hash256 = Hash256([0xff; 32]);
+
+    pub fn from_hex(hex: &str) -> Result<Hash256, String> {
+        parse_hex_256(hex).map(Hash256)
+    }
+
+    pub fn meets(&self, target: &Target) -> bool {
+        self.0 <= target.0
+    }
+
+    pub fn leading_zero_bits(&self) -> u32 {
+        leading_zero_bits(&self.0)
+    }
+}
+
+impl Target {
+    // Only an all zero hash meets it, which never happens in practice
+    pub const ZERO: Target = Target([0; 32]);
+    // Every hash meets it
+    pub const MAX: Target = Target([0xff; 32]);
+
+    pub fn from_hex(hex: &str) -> Result<Target, String> {
+        parse_hex_256(hex).map(Target)
+    }
+
+    /// Chance that a single hash meets the target, diff / 2^256.
+    pub fn probability(&self) -> f64 {
+        self.0.iter().rev().fold(0.0, |acc, &byte| (acc + byte as f64) / 256.0)
+    }
+
+    pub fn leading_zero_bits(&self) -> u32 {
+        leading_zero_bits(&self.0)
+    }
+}
+
+impl fmt::Display for Hash256 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&hex::encode(self.0))
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&hex::encode(self.0))
+    }
+}
+
+impl FromStr for Hash256 {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Hash256::from_hex(s)
+    }
+}
+
+impl FromStr for Target {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Target::from_hex(s)
+    }
+}
+
+// Parses up to 64 hex digits into a big-endian 256-bit value. The daemon sends
+// the diff without leading zeros, so shorter strings are left padded.
+fn parse_hex_256(hex: &str) -> Result<[u8; 32], String> {
+    let trimmed = hex.trim();
+    let digits = trimmed.strip_prefix("0x").unwrap_or(trimmed);
+    // Extra leading zeros don't change the value
+    let digits = if digits.len() > 64 {
+        match digits.trim_start_matches('0') {
+            "" => "0",
+            significant => significant,
+        }
+    } else {
+        digits
+    };
+    if digits.is_empty() || digits.len() > 64 {
+        return Err(format!("\"{}\" is not a 256-bit hex value", hex));
+    }
+    let mut padded = "0".repeat(64 - digits.len());
+    padded.push_str(digits);
+    let mut bytes = [0u8; 32];
+    hex::decode_to_slice(&padded, &mut bytes).map_err(|_| format!("\"{}\" is not a 256-bit hex value", hex))?;
+    Ok(bytes)
+}
+
+fn leading_zero_bits(bytes: &[u8; 32]) -> u32 {
+    let mut bits = 0;
+    for byte in bytes {
+        bits += byte.leading_zeros();
+        if *byte != 0 {
+            break;
+        }
+    }
+    bits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIFF: &str = "00000000ffff0000000000000000000000000000000000000000000000000001";
+
+    #[test]
+    fn hex_round_trips() {
+        let hash = Hash256::from_hex(DIFF).unwrap();
+        assert_eq!(hash.to_string(), DIFF);
+        assert_eq!(DIFF.parse::<Target>().unwrap().to_string(), DIFF);
+        assert_eq!(Hash256::MAX.to_string(), "f".repeat(64));
+    }
+
+    #[test]
+    fn short_input_is_left_padded() {
+        let target = Target::from_hex("fff").unwrap();
+        let mut expected = [0u8; 32];
+        expected[30] = 0x0f;
+        expected[31] = 0xff;
+        assert_eq!(target, Target(expected));
+        assert_eq!(target.to_string(), format!("{}fff", "0".repeat(61)));
+        assert_eq!(Target::from_hex("0").unwrap(), Target::ZERO);
+    }
+
+    #[test]
+    fn accepts_0x_prefix_and_whitespace() {
+        assert_eq!(Hash256::from_hex(&format!("0x{}", DIFF)).unwrap(), Hash256::from_hex(DIFF).unwrap());
+        assert_eq!(Target::from_hex(" 0xff\n").unwrap(), Target::from_hex("ff").unwrap());
+    }
+
+    #[test]
+    fn extra_leading_zeros_are_dropped() {
+        let long = format!("0000{}", DIFF);
+        assert_eq!(Target::from_hex(&long).unwrap(), Target::from_hex(DIFF).unwrap());
+        assert_eq!(Target::from_hex(&"0".repeat(80)).unwrap(), Target::ZERO);
+        // 65 significant digits don't fit
+        assert!(Target::from_hex(&format!("1{}", "0".repeat(64))).is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        for input in ["", "0x", "zz", "0xg1", "12 34", "-1", &format!("{}g", &DIFF[..63])] {
+            assert!(Hash256::from_hex(input).is_err(), "{:?} should not parse", input);
+        }
+    }
+
+    #[test]
+    fn counts_leading_zero_bits() {
+        assert_eq!(Hash256::MAX.leading_zero_bits(), 0);
+        assert_eq!(Hash256([0; 32]).leading_zero_bits(), 256);
+        assert_eq!(Hash256::from_hex(DIFF).unwrap().leading_zero_bits(), 32);
+        let mut bytes = [0xff; 32];
+        bytes[0] = 0;
+        bytes[1] = 0x01;
+        assert_eq!(Hash256(bytes).leading_zero_bits(), 15);
+        assert_eq!(Target::from_hex("0000ffff").unwrap().leading_zero_bits(), 240);
+    }
+
+    #[test]
+    fn meets_at_equality() {
+        let target = Target::from_hex(DIFF).unwrap();
+        let equal = Hash256(target.0);
+        assert!(equal.meets(&target));
+
+        let mut above = target.0;
+        above[31] += 1;
+        assert!(!Hash256(above).meets(&target));
+
+        let mut below = target.0;
+        below[31] -= 1;
+        assert!(Hash256(below).meets(&target));
+        assert!(Hash256::MAX.meets(&Target::MAX));
+        assert!(Hash256([0; 32]).meets(&Target::ZERO));
+    }
+}
@@ -0,0 +1,423 @@
+use std::fmt;
+
+use secp256k1::hashes::{sha256, Hash};
+use secp256k1::rand::RngCore;
+use secp256k1::rand::rngs::OsRng;
+
+pub const PUBKEY_LEN: usize = 65;
+const PUBKEY_HEX_LEN: usize = PUBKEY_LEN * 2;
+const HEX_CHARS: &[u8; 16] = b"0123456789abcdef";
+
+const H0: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+const K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+// Random inputs checked against the scalar hasher before a SIMD backend is used
+const SELF_TEST_ROUNDS: usize = 64;
+
+/// How candidate hashes sha256(hex(pubkey) + seed) are computed on the CPU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    Scalar,
+    Sse2,
+    Avx2,
+    Avx512,
+    ShaNi,
+}
+
+impl Backend {
+    /// Messages hashed in parallel by one call to the backend.
+    pub fn lanes(self) -> usize {
+        match self {
+            Backend::Scalar | Backend::ShaNi => 1,
+            Backend::Sse2 => 4,
+            Backend::Avx2 => 8,
+            Backend::Avx512 => 16,
+        }
+    }
+
+    pub fn is_supported(self) -> bool {
+        #[cfg(target_arch = "x86_64")]
+        {
+            match self {
+                Backend::Scalar => true,
+                Backend::Sse2 => is_x86_feature_detected!("sse2"),
+                Backend::Avx2 => is_x86_feature_detected!("avx2"),
+                Backend::Avx512 => is_x86_feature_detected!("avx512f"),
+                Backend::ShaNi => {
+                    is_x86_feature_detected!("sha")
+                        && is_x86_feature_detected!("ssse3")
+                        && is_x86_feature_detected!("sse4.1")
+                }
+            }
+        }
+        #[cfg(not(target_arch = "x86_64"))]
+        {
+            self == Backend::Scalar
+        }
+    }
+
+    /// Fastest backend the CPU supports.
+    pub fn detect() -> Backend {
+        [Backend::Avx512, Backend::ShaNi, Backend::Avx2, Backend::Sse2]
+            .into_iter()
+            .find(|backend| backend.is_supported())
+            .unwrap_or(Backend::Scalar)
+    }
+
+    /// Parses a `cpu_hasher` config value, `auto` picks with `detect`.
+    pub fn from_name(name: &str) -> Option<Backend> {
+        match name {
+            "auto" => Some(Backend::detect()),
+            "scalar" => Some(Backend::Scalar),
+            "sse2" => Some(Backend::Sse2),
+            "avx2" => Some(Backend::Avx2),
+            "avx512" => Some(Backend::Avx512),
+            "sha-ni" => Some(Backend::ShaNi),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Backend::Scalar => "scalar",
+            Backend::Sse2 => "sse2",
+            Backend::Avx2 => "avx2",
+            Backend::Avx512 => "avx512",
+            Backend::ShaNi => "sha-ni",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Hashes batches of candidate pubkeys against one seed. The padded message
+/// buffers are built once per seed and only the pubkey hex is rewritten for
+/// every candidate, so nothing is allocated in the hot loop.
+pub struct BatchHasher {
+    backend: Backend,
+    seed: String,
+    message_len: usize,
+    // One padded message per lane, all the same length
+    messages: Vec<Vec<u8>>,
+}
+
+impl BatchHasher {
+    pub fn new(backend: Backend) -> Self {
+        assert!(backend.is_supported(), "{} hashing is not supported by this CPU", backend);
+        let mut hasher = BatchHasher {
+            backend,
+            seed: String::new(),
+            message_len: 0,
+            messages: Vec::new(),
+        };
+        hasher.build_messages();
+        hasher
+    }
+
+    pub fn set_seed(&mut self, seed: &str) {
+        if self.seed != seed {
+            self.seed = seed.to_string();
+            self.build_messages();
+        }
+    }
+
+    fn build_messages(&mut self) {
+        self.message_len = PUBKEY_HEX_LEN + self.seed.len();
+        let padded_len = (self.message_len + 9).div_ceil(64) * 64;
+        let mut template = vec![0u8; padded_len];
+        template[PUBKEY_HEX_LEN..self.message_len].copy_from_slice(self.seed.as_bytes());
+        template[self.message_len] = 0x80;
+        template[padded_len - 8..].copy_from_slice(&((self.message_len as u64) * 8).to_be_bytes());
+        self.messages = vec![template; self.backend.lanes()];
+    }
+
+    /// Writes sha256(hex(pubkey) + seed) of every pubkey to `out`.
+    pub fn hash(&mut self, pubkeys: &[[u8; PUBKEY_LEN]], out: &mut [[u8; 32]]) {
+        assert_eq!(pubkeys.len(), out.len());
+        if self.backend == Backend::Scalar {
+            for (pubkey, hash) in pubkeys.iter().zip(out.iter_mut()) {
+                write_hex(pubkey, &mut self.messages[0][..PUBKEY_HEX_LEN]);
+                *hash = sha256::Hash::hash(&self.messages[0][..self.message_len]).to_byte_array();
+            }
+            return;
+        }
+
+        let lanes = self.backend.lanes();
+        for (pubkeys, out) in pubkeys.chunks(lanes).zip(out.chunks_mut(lanes)) {
+            for lane in 0..lanes {
+                // Short last chunk, the spare lanes just rehash the first pubkey
+                let pubkey = pubkeys.get(lane).unwrap_or(&pubkeys[0]);
+                write_hex(pubkey, &mut self.messages[lane][..PUBKEY_HEX_LEN]);
+            }
+            let mut states = [H0; 16];
+            compress(self.backend, &self.messages, &mut states[..lanes]);
+            for (state, hash) in states.iter().zip(out.iter_mut()) {
+                for (word, bytes) in state.iter().zip(hash.chunks_exact_mut(4)) {
+                    bytes.copy_from_slice(&word.to_be_bytes());
+                }
+            }
+        }
+    }
+
+    /// Compares this backend against the scalar hasher on random pubkeys and
+    /// seeds of varying length. Returns false on any mismatch.
+    pub fn self_test(&self) -> bool {
+        let mut tested = BatchHasher::new(self.backend);
+        let mut reference = BatchHasher::new(Backend::Scalar);
+        let mut rng = OsRng;
+        for round in 0..SELF_TEST_ROUNDS {
+            let mut seed = vec![0u8; round * 3];
+            rng.fill_bytes(&mut seed);
+            let seed = hex::encode(seed);
+            let mut pubkeys = vec![[0u8; PUBKEY_LEN]; 1 + round % 37];
+            for pubkey in pubkeys.iter_mut() {
+                rng.fill_bytes(pubkey);
+            }
+
+            let mut expected = vec![[0u8; 32]; pubkeys.len()];
+            let mut actual = vec![[0u8; 32]; pubkeys.len()];
+            reference.set_seed(&seed);
+            reference.hash(&pubkeys, &mut expected);
+            tested.set_seed(&seed);
+            tested.hash(&pubkeys, &mut actual);
+            if expected != actual {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+fn write_hex(bytes: &[u8], out: &mut [u8]) {
+    for (byte, chars) in bytes.iter().zip(out.chunks_exact_mut(2)) {
+        chars[0] = HEX_CHARS[(byte >> 4) as usize];
+        chars[1] = HEX_CHARS[(byte & 0x0F) as usize];
+    }
+}
+
+fn compress(backend: Backend, messages: &[Vec<u8>], states: &mut [[u32; 8]]) {
+    #[cfg(target_arch = "x86_64")]
+    // Safety: backends are only used after `is_supported` passed
+    unsafe {
+        match backend {
+            Backend::Sse2 => x86::sse2::compress(messages, states),
+            Backend::Avx2 => x86::avx2::compress(messages, states),
+            Backend::Avx512 => x86::avx512::compress(messages, states),
+            Backend::ShaNi => {
+                for (message, state) in messages.iter().zip(states.iter_mut()) {
+                    x86::compress_sha_ni(message, state);
+                }
+            }
+            Backend::Scalar => unreachable!("scalar hashing does not go through compress"),
+        }
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    unreachable!("{} is not available on this architecture", backend);
+}
+
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+    use std::arch::x86_64::*;
+
+    use super::K;
+
+    // Multi-buffer SHA-256 with one message per 32-bit vector lane
+    macro_rules! lanes_backend {
+        ($name:ident, $feature:literal, $lanes:literal, $vec:ty, $shift:ty,
+         $add:ident, $xor:ident, $and:ident, $andnot:ident, $or:ident,
+         $srli:ident, $slli:ident, $set1:ident, $loadu:ident, $storeu:ident) => {
+            pub mod $name {
+                use super::*;
+
+                #[inline]
+                #[target_feature(enable = $feature)]
+                fn ror<const R: $shift, const L: $shift>(x: $vec) -> $vec {
+                    $or($srli::<R>(x), $slli::<L>(x))
+                }
+
+                #[inline]
+                #[target_feature(enable = $feature)]
+                fn load(words: &[u32; $lanes]) -> $vec {
+                    unsafe { $loadu(words.as_ptr() as *const _) }
+                }
+
+                #[inline]
+                #[target_feature(enable = $feature)]
+                fn store(value: $vec) -> [u32; $lanes] {
+                    let mut words = [0u32; $lanes];
+                    unsafe { $storeu(words.as_mut_ptr() as *mut _, value) };
+                    words
+                }
+
+                #[target_feature(enable = $feature)]
+                pub fn compress(messages: &[Vec<u8>], states: &mut [[u32; 8]]) {
+                    let mut state = [$set1(0); 8];
+                    for (i, value) in state.iter_mut().enumerate() {
+                        *value = load(&std::array::from_fn(|lane| states[lane][i]));
+                    }
+
+                    for block in (0..messages[0].len()).step_by(64) {
+                        let mut w = [$set1(0); 16];
+                        for (t, word) in w.iter_mut().enumerate() {
+                            let offset = block + t * 4;
+                            *word = load(&std::array::from_fn(|lane| {
+                                let bytes = &messages[lane][offset..offset + 4];
+                                u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+                            }));
+                        }
+
+                        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = state;
+                        for t in 0..64 {
+                            let wt = if t < 16 {
+                                w[t]
+                            } else {
+                                let w15 = w[(t - 15) & 15];
+                                let w2 = w[(t - 2) & 15];
+                                let s0 = $xor($xor(ror::<7, 25>(w15), ror::<18, 14>(w15)), $srli::<3>(w15));
+                                let s1 = $xor($xor(ror::<17, 15>(w2), ror::<19, 13>(w2)), $srli::<10>(w2));
+                                let value = $add($add(w[t & 15], s0), $add(w[(t - 7) & 15], s1));
+                                w[t & 15] = value;
+                                value
+                            };
+                            let ep1 = $xor($xor(ror::<6, 26>(e), ror::<11, 21>(e)), ror::<25, 7>(e));
+                            let ch = $xor($and(e, f), $andnot(e, g));
+                            let t1 = $add($add(h, ep1), $add($add(ch, $set1(K[t] as i32)), wt));
+                            let ep0 = $xor($xor(ror::<2, 30>(a), ror::<13, 19>(a)), ror::<22, 10>(a));
+                            let maj = $or($and(a, b), $and(c, $or(a, b)));
+                            let t2 = $add(ep0, maj);
+                            h = g;
+                            g = f;
+                            f = e;
+                            e = $add(d, t1);
+                            d = c;
+                            c = b;
+                            b = a;
+                            a = $add(t1, t2);
+                        }
+
+                        for (value, round) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+                            *value = $add(*value, round);
+                        }
+                    }
+
+                    for (i, value) in state.into_iter().enumerate() {
+                        for (lane, word) in store(value).into_iter().enumerate() {
+                            states[lane][i] = word;
+                        }
+                    }
+                }
+            }
+        };
+    }
+
+    lanes_backend!(sse2, "sse2", 4, __m128i, i32,
+        _mm_add_epi32, _mm_xor_si128, _mm_and_si128, _mm_andnot_si128, _mm_or_si128,
+        _mm_srli_epi32, _mm_slli_epi32, _mm_set1_epi32, _mm_loadu_si128, _mm_storeu_si128);
+    lanes_backend!(avx2, "avx2", 8, __m256i, i32,
+        _mm256_add_epi32, _mm256_xor_si256, _mm256_and_si256, _mm256_andnot_si256, _mm256_or_si256,
+        _mm256_srli_epi32, _mm256_slli_epi32, _mm256_set1_epi32, _mm256_loadu_si256, _mm256_storeu_si256);
+    lanes_backend!(avx512, "avx512f", 16, __m512i, u32,
+        _mm512_add_epi32, _mm512_xor_si512, _mm512_and_si512, _mm512_andnot_si512, _mm512_or_si512,
+        _mm512_srli_epi32, _mm512_slli_epi32, _mm512_set1_epi32, _mm512_loadu_si512, _mm512_storeu_si512);
+
+    /// Single-buffer SHA-256 using the SHA extensions, four rounds per step.
+    #[target_feature(enable = "sha,sse2,ssse3,sse4.1")]
+    pub unsafe fn compress_sha_ni(message: &[u8], state: &mut [u32; 8]) {
+        unsafe {
+            let shuffle_mask = _mm_set_epi64x(0x0c0d0e0f08090a0b, 0x0405060700010203);
+
+            // ABEF / CDGH layout expected by sha256rnds2
+            let dcba = _mm_loadu_si128(state.as_ptr() as *const __m128i);
+            let hgfe = _mm_loadu_si128(state.as_ptr().add(4) as *const __m128i);
+            let cdab = _mm_shuffle_epi32(dcba, 0xB1);
+            let efgh = _mm_shuffle_epi32(hgfe, 0x1B);
+            let mut abef = _mm_alignr_epi8(cdab, efgh, 8);
+            let mut cdgh = _mm_blend_epi16(efgh, cdab, 0xF0);
+
+            for block in message.chunks_exact(64) {
+                let abef_save = abef;
+                let cdgh_save = cdgh;
+
+                let mut msgs = [_mm_setzero_si128(); 4];
+                for (i, msg) in msgs.iter_mut().enumerate() {
+                    let words = _mm_loadu_si128(block.as_ptr().add(i * 16) as *const __m128i);
+                    *msg = _mm_shuffle_epi8(words, shuffle_mask);
+                }
+
+                for group in 0..16 {
+                    if group >= 4 {
+                        // W[t..t+4] from W[t-16..t-12], W[t-15..], W[t-7..] and W[t-4..]
+                        let w16 = msgs[group % 4];
+                        let w12 = msgs[(group + 1) % 4];
+                        let w8 = msgs[(group + 2) % 4];
+                        let w4 = msgs[(group + 3) % 4];
+                        let partial = _mm_add_epi32(_mm_sha256msg1_epu32(w16, w12), _mm_alignr_epi8(w4, w8, 4));
+                        msgs[group % 4] = _mm_sha256msg2_epu32(partial, w4);
+                    }
+                    let k = _mm_loadu_si128(K.as_ptr().add(group * 4) as *const __m128i);
+                    let wk = _mm_add_epi32(msgs[group % 4], k);
+                    cdgh = _mm_sha256rnds2_epu32(cdgh, abef, wk);
+                    abef = _mm_sha256rnds2_epu32(abef, cdgh, _mm_shuffle_epi32(wk, 0x0E));
+                }
+
+                abef = _mm_add_epi32(abef, abef_save);
+                cdgh = _mm_add_epi32(cdgh, cdgh_save);
+            }
+
+            let feba = _mm_shuffle_epi32(abef, 0x1B);
+            let dchg = _mm_shuffle_epi32(cdgh, 0xB1);
+            let dcba = _mm_blend_epi16(feba, dchg, 0xF0);
+            let hgfe = _mm_alignr_epi8(dchg, feba, 8);
+            _mm_storeu_si128(state.as_mut_ptr() as *mut __m128i, dcba);
+            _mm_storeu_si128(state.as_mut_ptr().add(4) as *mut __m128i, hgfe);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The secp256k1 generator, uncompressed
+    const G: &str = "0479be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798483ada7726a3c4655da4fbfc0e1108a8fd17b448a68554199c47d08ffb10d4b8";
+
+    #[test]
+    fn scalar_matches_known_answers() {
+        let pubkey: [u8; PUBKEY_LEN] = hex::decode(G).unwrap().try_into().unwrap();
+        let mut hasher = BatchHasher::new(Backend::Scalar);
+        let mut out = [[0u8; 32]];
+        // sha256 of the lowercase hex pubkey followed by the seed
+        for (seed, expected) in [
+            ("9de302fcc661cd5b55d186cdc579cb9e", "d9e2818e288a3ef6de2285e5c585adb48fcdf17e7f779095ff21c7fbee079bea"),
+            ("", "b3a373ff6d59118736ecbcc2de113504a9c4e115aede71f2384ce2890465fee7"),
+        ] {
+            hasher.set_seed(seed);
+            hasher.hash(&[pubkey], &mut out);
+            assert_eq!(hex::encode(out[0]), expected, "seed {:?}", seed);
+        }
+    }
+
+    #[test]
+    fn simd_backends_match_scalar() {
+        for backend in [Backend::Sse2, Backend::Avx2, Backend::Avx512, Backend::ShaNi] {
+            if !backend.is_supported() {
+                println!("{} not supported here, skipped", backend);
+                continue;
+            }
+            assert!(BatchHasher::new(backend).self_test(), "{} disagrees with scalar", backend);
+        }
+    }
+}
@@ -0,0 +1,293 @@
+use colored::*;
+
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use reqwest::{Client, Response};
+use tokio::sync::Notify;
+use tokio::time;
+
+use crate::failover::ServerList;
+use crate::get_job::{get_job, get_job_failover, parse_job, Job, JobError, JobPublisher};
+use crate::job_timing::ClockSample;
+use crate::util::is_unsupported;
+
+// Path of the server-sent events stream on the daemon
+const STREAM_PATH: &str = "/challenge-events";
+// The daemon sends keepalive comments, a stream silent for this long is dead
+const STREAM_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+// How long to stay on a backup stream before looking for the primary again
+const PRIMARY_RECHECK: Duration = Duration::from_secs(60);
+
+/// Where jobs come from. The job task awaits `next_job` in a loop, publishes
+/// whatever it returns and backs off on errors, so sources only deal with
+/// pacing and transport.
+pub trait JobSource {
+    fn next_job(&mut self) -> impl Future<Output = Result<Job, JobError>> + Send;
+}
+
+/// Fetches `/get-challenge` every `interval`, unless handed a job in between.
+pub struct PollingJobSource {
+    servers: Arc<ServerList>,
+    interval: Duration,
+    last_fetch: Option<Instant>,
+    handoff: JobHandoff,
+}
+
+/// Gives the job source a job fetched elsewhere, which goes out right away
+/// instead of being fetched again on the next poll, or asks it to check the
+/// current job early.
+#[derive(Clone, Default)]
+pub struct JobHandoff {
+    job: Arc<Mutex<Option<Job>>>,
+    wake: Arc<Notify>,
+}
+
+impl JobHandoff {
+    pub fn new() -> Self {
+        JobHandoff { job: Arc::new(Mutex::new(None)), wake: Arc::new(Notify::new()) }
+    }
+
+    pub fn deliver(&self, job: Job) {
+        *self.job.lock().unwrap() = Some(job);
+        self.wake.notify_one();
+    }
+
+    /// Fetches the job again without waiting for the next poll.
+    pub fn refresh(&self) {
+        self.wake.notify_one();
+    }
+
+    fn take(&self) -> Option<Job> {
+        self.job.lock().unwrap().take()
+    }
+}
+
+impl PollingJobSource {
+    pub fn new(servers: Arc<ServerList>, interval: Duration, handoff: JobHandoff) -> Self {
+        PollingJobSource { servers, interval, last_fetch: None, handoff }
+    }
+}
+
+impl JobSource for PollingJobSource {
+    async fn next_job(&mut self) -> Result<Job, JobError> {
+        // A retry after a failed fetch may already have waited long enough
+        if let Some(last_fetch) = self.last_fetch {
+            tokio::select! {
+                _ = time::sleep_until((last_fetch + self.interval).into()) => {}
+                _ = self.handoff.wake.notified() => {}
+            }
+        }
+        // Handed over jobs don't move the poll schedule
+        if let Some(job) = self.handoff.take() {
+            return Ok(job);
+        }
+        self.last_fetch = Some(Instant::now());
+        get_job_failover(&self.servers).await
+    }
+}
+
+/// Receives jobs pushed by the daemon as server-sent events, so a new
+/// challenge reaches the miners as soon as it exists instead of on the next
+/// poll. Daemons without the stream are polled instead.
+pub struct SseJobSource {
+    servers: Arc<ServerList>,
+    client: Client,
+    stream: Option<EventStream>,
+    polling: PollingJobSource,
+    polling_only: bool,
+    handoff: JobHandoff,
+}
+
+struct EventStream {
+    index: usize,
+    server: String,
+    response: Response,
+    connected: Instant,
+    buffer: Vec<u8>,
+    data: String,
+    // Taken when the stream was opened, pushed jobs carry no headers of their own
+    clock: Option<ClockSample>,
+}
+
+impl SseJobSource {
+    pub fn new(servers: Arc<ServerList>, poll_interval: Duration, handoff: JobHandoff) -> Self {
+        let client = Client::builder()
+            .connect_timeout(Duration::from_secs(10))
+            .build()
+            .unwrap();
+        SseJobSource {
+            polling: PollingJobSource::new(Arc::clone(&servers), poll_interval, handoff.clone()),
+            servers,
+            client,
+            stream: None,
+            polling_only: false,
+            handoff,
+        }
+    }
+
+    // Opens the stream on the first server that answers. Returns None when
+    // the daemon doesn't offer one and the source switched to polling.
+    async fn connect(&mut self) -> Result<Option<EventStream>, JobError> {
+        let mut last_error = None;
+        for (index, server) in self.servers.candidates() {
+            let request = self.client
+                .get(format!("{}{}", server, STREAM_PATH))
+                .header("Accept", "text/event-stream");
+            let sent = SystemTime::now();
+            match request.send().await {
+                Ok(response) if is_event_stream(&response) => {
+                    self.servers.report_success(index);
+                    println!("\n{} Receiving jobs from {}{}", "[INFO]".blue(), server, STREAM_PATH);
+                    let clock = ClockSample::from_headers(response.headers(), sent, SystemTime::now());
+                    return Ok(Some(EventStream {
+                        index,
+                        server,
+                        response,
+                        connected: Instant::now(),
+                        buffer: vec![],
+                        data: String::new(),
+                        clock,
+                    }));
+                }
+                Ok(response) if is_unsupported(response.status()) || response.status().is_success() => {
+                    self.servers.report_success(index);
+                    println!("\n{} {} doesn't push jobs ({}), falling back to polling", "[WARN]".yellow(), server, response.status());
+                    self.polling_only = true;
+                    return Ok(None);
+                }
+                Ok(response) => {
+                    last_error = Some(JobError::HttpStatus(response.status()));
+                    self.servers.report_failure(index);
+                }
+                Err(e) => {
+                    last_error = Some(JobError::Request(e));
+                    self.servers.report_failure(index);
+                }
+            }
+        }
+        Err(last_error.unwrap())
+    }
+}
+
+impl JobSource for SseJobSource {
+    async fn next_job(&mut self) -> Result<Job, JobError> {
+        loop {
+            if self.polling_only {
+                return self.polling.next_job().await;
+            }
+            if self.stream.is_none() {
+                match self.connect().await? {
+                    Some(stream) => self.stream = Some(stream),
+                    None => continue,
+                }
+            }
+            let stream = self.stream.as_mut().unwrap();
+            let event = tokio::select! {
+                event = stream.next_event() => event,
+                _ = self.handoff.wake.notified() => {
+                    if let Some(job) = self.handoff.take() {
+                        return Ok(job);
+                    }
+                    // The stream may have missed a new challenge, ask its server directly
+                    match get_job(stream.server.clone()).await {
+                        Ok(job) => return Ok(job),
+                        Err(e) => println!("\n{} Couldn't check the job with {}: {}", "[WARN]".yellow(), stream.server, e),
+                    }
+                    continue;
+                }
+            };
+            match event {
+                Ok(Some(job)) => return Ok(job),
+                // Reconnect, which prefers the primary again if it is healthy
+                Ok(None) => self.stream = None,
+                Err(e) => {
+                    self.servers.report_failure(stream.index);
+                    self.stream = None;
+                    return Err(e);
+                }
+            }
+        }
+    }
+}
+
+impl EventStream {
+    // Reads until a complete job event arrives. Returns None when it is time
+    // to leave a backup server and look for the primary.
+    async fn next_event(&mut self) -> Result<Option<Job>, JobError> {
+        loop {
+            while let Some(end) = self.buffer.iter().position(|byte| *byte == b'\n') {
+                let line: Vec<u8> = self.buffer.drain(..=end).collect();
+                let line = String::from_utf8_lossy(&line);
+                let line = line.trim_end_matches(['\r', '\n']);
+                if line.is_empty() {
+                    // A blank line ends the event
+                    if self.data.is_empty() {
+                        continue;
+                    }
+                    let data = std::mem::take(&mut self.data);
+                    // A bad event doesn't mean the stream is bad, keep reading
+                    match parse_job(&data) {
+                        Ok(mut job) => {
+                            job.clock = self.clock;
+                            return Ok(Some(job));
+                        }
+                        Err(e) => println!("\n{} Ignoring invalid job event: {}", "[WARN]".yellow(), e),
+                    }
+                } else if let Some(value) = line.strip_prefix("data:") {
+                    if !self.data.is_empty() {
+                        self.data.push('\n');
+                    }
+                    self.data.push_str(value.strip_prefix(' ').unwrap_or(value));
+                }
+                // Event names, ids and ":" keepalive comments carry nothing we need
+            }
+
+            if self.index != 0 && self.connected.elapsed() >= PRIMARY_RECHECK {
+                return Ok(None);
+            }
+            match time::timeout(STREAM_IDLE_TIMEOUT, self.response.chunk()).await {
+                Ok(Ok(Some(chunk))) => self.buffer.extend_from_slice(&chunk),
+                Ok(Ok(None)) => return Err(JobError::Stream(String::from("job stream closed by the server"))),
+                Ok(Err(e)) => return Err(JobError::Request(e)),
+                Err(_) => return Err(JobError::Stream(format!("job stream silent for {}s", STREAM_IDLE_TIMEOUT.as_secs()))),
+            }
+        }
+    }
+}
+
+fn is_event_stream(response: &Response) -> bool {
+    response.status().is_success()
+        && response
+            .headers()
+            .get("Content-Type")
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.starts_with("text/event-stream"))
+}
+
+/// Checks every `interval` whether the challenge being mined is still open
+/// and pauses the miners as soon as the network solved it, instead of hashing
+/// a dead seed until the next poll. The new challenge goes to the job source
+/// through `handoff`. Only the server in use is asked and its health is left
+/// alone, outages are the job source's to notice.
+pub async fn watch_challenge(servers: Arc<ServerList>, job_publisher: Arc<JobPublisher>, interval: Duration, handoff: JobHandoff) {
+    loop {
+        time::sleep(interval).await;
+        let current = job_publisher.current();
+        if !current.is_active() {
+            continue;
+        }
+        let server = match servers.candidates().into_iter().next() {
+            Some((_, server)) => server,
+            None => continue,
+        };
+        let job = match get_job(server).await {
+            Ok(job) => job,
+            Err(_) => continue,
+        };
+        if job.seed != current.job.seed && job_publisher.pause(current.epoch) {
+            println!("\n{} Challenge {} was solved by the network, moving on to {}", "[INFO]".blue(), current.job.seed, job.seed);
+            handoff.deliver(job);
+        }
+    }
+}
@@ -0,0 +1,158 @@
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+use reqwest::header::{HeaderMap, DATE};
+
+use crate::get_job::Job;
+use crate::util::{unix_millis, unix_millis_at};
+
+// A job open this many times longer than a coin should take us probably
+// isn't the daemon's current one anymore
+const STALE_AFTER_EXPECTED: f64 = 10.0;
+
+// Weight of a new offset sample, Date headers only have second resolution
+// so single samples are noisy
+const SMOOTHING: f64 = 0.2;
+
+/// How far the daemon's clock was ahead of ours when it answered a request,
+/// read from the response's `Date` header.
+#[derive(Debug, Clone, Copy)]
+pub struct ClockSample {
+    offset_ms: i64,
+}
+
+impl ClockSample {
+    /// `sent` and `received` bracket the request, the daemon stamped the
+    /// response somewhere in between. None without a usable `Date` header.
+    pub fn from_headers(headers: &HeaderMap, sent: SystemTime, received: SystemTime) -> Option<ClockSample> {
+        let date = headers.get(DATE)?.to_str().ok()?;
+        let server = httpdate::parse_http_date(date).ok()?;
+        // The header is truncated to the second, the middle of it is the best guess
+        let server_ms = unix_millis_at(server) as i64 + 500;
+        let local_ms = (unix_millis_at(sent) as i64 + unix_millis_at(received) as i64) / 2;
+        Some(ClockSample { offset_ms: server_ms - local_ms })
+    }
+}
+
+/// Keeps a smoothed estimate of the daemon's clock offset so job ages, which
+/// the daemon gives as its own `lastFound` timestamp, come out right when the
+/// clocks disagree. Ages are clamped at zero, a `lastFound` in our future is
+/// a job that was just found.
+#[derive(Default)]
+pub struct JobClock {
+    // Milliseconds the daemon is ahead of us, None until the first sample
+    offset_ms: Mutex<Option<f64>>,
+}
+
+impl JobClock {
+    pub fn new() -> Self {
+        JobClock { offset_ms: Mutex::new(None) }
+    }
+
+    pub fn observe(&self, sample: ClockSample) {
+        let mut offset = self.offset_ms.lock().unwrap();
+        *offset = Some(match *offset {
+            Some(offset) => offset + (sample.offset_ms as f64 - offset) * SMOOTHING,
+            None => sample.offset_ms as f64,
+        });
+    }
+
+    /// Milliseconds the daemon's clock is ahead of ours, 0 until it answered once.
+    pub fn offset_ms(&self) -> i64 {
+        self.offset_ms.lock().unwrap().unwrap_or(0.0).round() as i64
+    }
+
+    /// The daemon's current time in Unix milliseconds.
+    pub fn server_now_ms(&self) -> u64 {
+        (unix_millis() as i64 + self.offset_ms()).max(0) as u64
+    }
+
+    /// Time since the job's challenge was last solved, by the daemon's clock.
+    pub fn job_age(&self, job: &Job) -> Duration {
+        Duration::from_millis(self.server_now_ms().saturating_sub(job.last_found))
+    }
+
+    /// Whether the job has been open so much longer than the `expected_secs`
+    /// a coin takes us that the daemon likely replaced it without us hearing.
+    pub fn is_stale(&self, job: &Job, expected_secs: f64) -> bool {
+        self.job_age(job).as_secs_f64() > expected_secs * STALE_AFTER_EXPECTED
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+    use std::time::UNIX_EPOCH;
+
+    use crate::hash256::Target;
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    fn date(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(DATE, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    fn job(last_found: u64) -> Job {
+        Job { seed: String::from("seed"), diff: Target::MAX, reward: 1.0, last_found, clock: None }
+    }
+
+    fn sample(offset_ms: i64) -> ClockSample {
+        ClockSample { offset_ms }
+    }
+
+    #[test]
+    fn no_sample_without_a_usable_date() {
+        let now = at(1_700_000_000);
+        assert!(ClockSample::from_headers(&HeaderMap::new(), now, now).is_none());
+        assert!(ClockSample::from_headers(&date("yesterday"), now, now).is_none());
+    }
+
+    #[test]
+    fn offset_from_the_date_header() {
+        let now = at(1_700_000_000);
+        // Half a second added for the truncated header
+        let ahead = ClockSample::from_headers(&date(&httpdate::fmt_http_date(at(1_700_000_030))), now, now).unwrap();
+        assert_eq!(ahead.offset_ms, 30_500);
+        let behind = ClockSample::from_headers(&date(&httpdate::fmt_http_date(at(1_699_999_970))), now, now).unwrap();
+        assert_eq!(behind.offset_ms, -29_500);
+        // Local time is the middle of the request
+        let slow = ClockSample::from_headers(&date(&httpdate::fmt_http_date(now)), at(1_699_999_999), at(1_700_000_001)).unwrap();
+        assert_eq!(slow.offset_ms, 500);
+    }
+
+    #[test]
+    fn offset_is_smoothed() {
+        let clock = JobClock::new();
+        assert_eq!(clock.offset_ms(), 0);
+        clock.observe(sample(10_000));
+        assert_eq!(clock.offset_ms(), 10_000);
+        clock.observe(sample(20_000));
+        assert_eq!(clock.offset_ms(), 12_000);
+    }
+
+    #[test]
+    fn job_age_follows_the_daemon_clock() {
+        let clock = JobClock::new();
+        clock.observe(sample(60_000));
+        // Found 30s ago by a daemon a minute ahead, in our future by our clock
+        let age = clock.job_age(&job(unix_millis() + 30_000));
+        assert!(age >= Duration::from_secs(29) && age <= Duration::from_secs(31), "{:?}", age);
+
+        let clock = JobClock::new();
+        clock.observe(sample(-60_000));
+        let age = clock.job_age(&job(unix_millis() - 90_000));
+        assert!(age >= Duration::from_secs(29) && age <= Duration::from_secs(31), "{:?}", age);
+    }
+
+    #[test]
+    fn future_last_found_is_just_found() {
+        let clock = JobClock::new();
+        assert_eq!(clock.job_age(&job(unix_millis() + 3_600_000)), Duration::ZERO);
+        clock.observe(sample(-60_000));
+        assert_eq!(clock.job_age(&job(unix_millis())), Duration::ZERO);
+    }
+}
@@ -0,0 +1,272 @@
+use colored::*;
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::get_job::Job;
+use crate::util::unix_millis;
+
+const JOURNAL_FILE: &str = "jobs.jsonl";
+
+/// One line of the journal, written once the job has been replaced.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub seed: String,
+    pub diff: String,
+    pub reward: f64,
+    pub last_found: u64,
+    // Unix milliseconds
+    pub received_at: u64,
+    pub replaced_at: u64,
+    pub hashes: u64,
+    // We got a coin for it
+    pub solved: bool,
+}
+
+struct OpenJob {
+    entry: JournalEntry,
+    hashes_at_start: u64,
+    // Submissions sent but not answered yet
+    in_flight: usize,
+}
+
+#[derive(Default)]
+struct JournalState {
+    current: Option<OpenJob>,
+    // Replaced jobs that still wait for a submission result before being written
+    awaiting: Vec<OpenJob>,
+}
+
+/// Appends every job we mined to `{data_dir}/jobs.jsonl` when its seed is
+/// replaced. A job that is replaced while one of our solutions for it is still
+/// being submitted is written once the daemon answers, so the daemon rotating
+/// the seed before our submission returns doesn't lose the solve.
+pub struct JobJournal {
+    path: PathBuf,
+    state: Mutex<JournalState>,
+}
+
+impl JobJournal {
+    pub fn new(data_dir: &str) -> Self {
+        JobJournal {
+            path: PathBuf::from(data_dir).join(JOURNAL_FILE),
+            state: Mutex::new(JournalState::default()),
+        }
+    }
+
+    /// Closes the current job and starts tracking `job`. `total_hashes` is
+    /// the miner wide hash counter at the moment of the switch.
+    pub fn replace(&self, job: &Job, total_hashes: u64) {
+        let now = unix_millis();
+        let mut state = self.state.lock().unwrap();
+        if let Some(mut previous) = state.current.take() {
+            previous.entry.replaced_at = now;
+            previous.entry.hashes = total_hashes - previous.hashes_at_start;
+            if previous.in_flight == 0 {
+                self.append(&previous.entry);
+            } else {
+                state.awaiting.push(previous);
+            }
+        }
+        state.current = Some(OpenJob {
+            entry: JournalEntry {
+                seed: job.seed.clone(),
+                diff: job.diff.to_string(),
+                reward: job.reward,
+                last_found: job.last_found,
+                received_at: now,
+                replaced_at: 0,
+                hashes: 0,
+                solved: false,
+            },
+            hashes_at_start: total_hashes,
+            in_flight: 0,
+        });
+    }
+
+    pub fn submission_started(&self, seed: &str) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(job) = state.find(seed) {
+            job.in_flight += 1;
+        }
+    }
+
+    pub fn submission_finished(&self, seed: &str, accepted: bool) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(job) = state.find(seed) {
+            job.in_flight = job.in_flight.saturating_sub(1);
+            job.entry.solved |= accepted;
+        }
+        if let Some(index) = state.awaiting.iter().position(|job| job.entry.seed == seed && job.in_flight == 0) {
+            let job = state.awaiting.remove(index);
+            self.append(&job.entry);
+        }
+    }
+
+    fn append(&self, entry: &JournalEntry) {
+        let result = self.path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| OpenOptions::new().create(true).append(true).open(&self.path))
+            .and_then(|mut file| writeln!(file, "{}", serde_json::to_string(entry).unwrap()));
+        if let Err(e) = result {
+            println!("\n{} Failed to write job journal {}: {}", "[ERROR]".red(), self.path.display(), e);
+        }
+    }
+}
+
+impl JournalState {
+    fn find(&mut self, seed: &str) -> Option<&mut OpenJob> {
+        self.current
+            .iter_mut()
+            .chain(self.awaiting.iter_mut())
+            .find(|job| job.entry.seed == seed)
+    }
+}
+
+/// Totals over the journal entries.
+#[derive(Debug, PartialEq)]
+struct Summary {
+    jobs: usize,
+    solved: usize,
+    lifetime_ms: u64,
+    hashes: u64,
+    mined: f64,
+}
+
+impl Summary {
+    fn of(entries: &[JournalEntry]) -> Self {
+        Summary {
+            jobs: entries.len(),
+            solved: entries.iter().filter(|entry| entry.solved).count(),
+            lifetime_ms: entries.iter().map(|entry| entry.replaced_at.saturating_sub(entry.received_at)).sum(),
+            hashes: entries.iter().map(|entry| entry.hashes).sum(),
+            mined: entries.iter().filter(|entry| entry.solved).map(|entry| entry.reward).sum(),
+        }
+    }
+}
+
+// Every readable entry of the journal at `path`, unreadable lines are skipped with a warning
+fn read_entries(path: &Path) -> io::Result<Vec<JournalEntry>> {
+    let text = fs::read_to_string(path)?;
+    let mut entries = vec![];
+    for (number, line) in text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+        match serde_json::from_str::<JournalEntry>(line) {
+            Ok(entry) => entries.push(entry),
+            Err(e) => println!("{} Skipping line {} of {}: {}", "[WARN]".yellow(), number + 1, path.display(), e),
+        }
+    }
+    Ok(entries)
+}
+
+/// Prints what the journal in `data_dir` says about past jobs.
+pub fn print_summary(data_dir: &str) {
+    let path = PathBuf::from(data_dir).join(JOURNAL_FILE);
+    let entries = match read_entries(&path) {
+        Ok(entries) => entries,
+        Err(e) => {
+            println!("{} Can't read job journal {}: {}", "[ERROR]".red(), path.display(), e);
+            return;
+        }
+    };
+    if entries.is_empty() {
+        println!("{} No jobs recorded in {} yet", "[INFO]".blue(), path.display());
+        return;
+    }
+
+    let Summary { jobs, solved, lifetime_ms, hashes, mined } = Summary::of(&entries);
+    println!("{} Jobs recorded: {}", "[INFO]".blue(), jobs);
+    println!("{} Average job lifetime: {:.1}s", "[INFO]".blue(), lifetime_ms as f64 / jobs as f64 / 1e3);
+    println!(
+        "{} Solved by us: {} of {} ({:.2}%), {}CLCs",
+        "[INFO]".blue(),
+        solved,
+        jobs,
+        solved as f64 * 100.0 / jobs as f64,
+        mined.to_string().green()
+    );
+    println!("{} Hashes spent: {} ({:.0} per job)", "[INFO]".blue(), hashes, hashes as f64 / jobs as f64);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash256::Target;
+    use crate::util::test_dir;
+
+    fn job(seed: &str, reward: f64) -> Job {
+        Job { seed: seed.to_string(), diff: Target::MAX, reward, last_found: 0, clock: None }
+    }
+
+    fn written(dir: &str) -> Vec<JournalEntry> {
+        read_entries(&PathBuf::from(dir).join(JOURNAL_FILE)).unwrap_or_default()
+    }
+
+    #[test]
+    fn replaced_jobs_are_written_with_their_hashes() {
+        let dir = test_dir("journal-replaced").display().to_string();
+        let journal = JobJournal::new(&dir);
+        journal.replace(&job("a", 1.0), 100);
+        assert!(written(&dir).is_empty());
+        journal.replace(&job("b", 1.0), 350);
+
+        let entries = written(&dir);
+        assert_eq!(entries.len(), 1);
+        assert_eq!((entries[0].seed.as_str(), entries[0].hashes, entries[0].solved), ("a", 250, false));
+        assert!(entries[0].replaced_at >= entries[0].received_at);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn jobs_replaced_mid_submission_wait_for_the_answer() {
+        let dir = test_dir("journal-in-flight").display().to_string();
+        let journal = JobJournal::new(&dir);
+        journal.replace(&job("a", 2.0), 0);
+        journal.submission_started("a");
+        journal.submission_started("a");
+        // The daemon rotates the seed before it answers
+        journal.replace(&job("b", 2.0), 10);
+        assert!(written(&dir).is_empty());
+
+        journal.submission_finished("a", true);
+        assert!(written(&dir).is_empty(), "written with a submission still in flight");
+        journal.submission_finished("a", false);
+        let entries = written(&dir);
+        assert_eq!(entries.len(), 1);
+        assert_eq!((entries[0].seed.as_str(), entries[0].hashes, entries[0].solved), ("a", 10, true));
+
+        // Answers for the current job don't write it
+        journal.submission_started("b");
+        journal.submission_finished("b", true);
+        assert_eq!(written(&dir).len(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn summary_counts_jobs_solves_and_rewards() {
+        let dir = test_dir("journal-summary").display().to_string();
+        let journal = JobJournal::new(&dir);
+        journal.replace(&job("a", 1.5), 0);
+        journal.submission_started("a");
+        journal.submission_finished("a", true);
+        journal.replace(&job("b", 4.0), 100);
+        journal.replace(&job("c", 2.5), 300);
+        journal.submission_started("c");
+        journal.submission_finished("c", true);
+        journal.replace(&job("d", 1.0), 600);
+        // A torn line from a crash is skipped
+        let path = PathBuf::from(&dir).join(JOURNAL_FILE);
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        writeln!(file, "{{\"seed\":\"torn").unwrap();
+
+        let entries = read_entries(&path).unwrap();
+        let summary = Summary::of(&entries);
+        assert_eq!((summary.jobs, summary.solved, summary.hashes, summary.mined), (3, 2, 600, 4.0));
+        let lifetime_ms: u64 = entries.iter().map(|entry| entry.replaced_at - entry.received_at).sum();
+        assert_eq!(summary.lifetime_ms, lifetime_ms);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,119 @@
+use colored::*;
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+
+use crate::outbox::{write_atomic, Outbox};
+use crate::util::unix_millis;
+
+const KEYS_DIR: &str = "keys";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyStatus {
+    // Written before the first submission, the outcome isn't known yet
+    Pending,
+    Mined,
+    Rejected,
+}
+
+/// The private key of a found solution, on disk before anything is sent so
+/// a crash or an unreadable answer can't lose a coin the daemon credited.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct KeyRecord {
+    pub private_key: String,
+    pub public_key: String,
+    pub hash: String,
+    pub seed: String,
+    // Unix milliseconds
+    pub found_at: u64,
+    pub status: KeyStatus,
+    // Set once mined, None for coins that went to a pool
+    pub coin_id: Option<u64>,
+}
+
+/// One record per found solution in `{data_dir}/keys`.
+pub struct KeyStore {
+    dir: PathBuf,
+}
+
+impl KeyStore {
+    pub fn new(data_dir: &str) -> Self {
+        KeyStore { dir: PathBuf::from(data_dir).join(KEYS_DIR) }
+    }
+
+    pub fn record(&self, record: &KeyRecord) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        write_atomic(&self.path(&record.hash), serde_json::to_string_pretty(record).unwrap().as_bytes())
+    }
+
+    /// Marks the key for `hash` with the daemon's answer.
+    pub fn finalize(&self, hash: &str, status: KeyStatus, coin_id: Option<u64>) {
+        let path = self.path(hash);
+        let result = read_record(&path).and_then(|mut record| {
+            record.status = status;
+            record.coin_id = coin_id;
+            self.record(&record).map_err(|e| e.to_string())
+        });
+        if let Err(e) = result {
+            println!("{} Failed to finalize key record {}: {}", "[ERROR]".red(), path.display(), e);
+        }
+    }
+
+    fn path(&self, hash: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", hash))
+    }
+}
+
+fn read_record(path: &Path) -> Result<KeyRecord, String> {
+    let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    serde_json::from_str(&text).map_err(|e| e.to_string())
+}
+
+/// Lists the keys in `data_dir` whose submission outcome is unknown, for
+/// recovering coins after a crash or a failed submission.
+pub fn print_pending(data_dir: &str) {
+    let dir = PathBuf::from(data_dir).join(KEYS_DIR);
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            println!("{} Can't read key records in {}: {}", "[ERROR]".red(), dir.display(), e);
+            return;
+        }
+    };
+    let mut pending = vec![];
+    for path in entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()) {
+        if path.extension().is_none_or(|extension| extension != "json") {
+            continue;
+        }
+        match read_record(&path) {
+            Ok(record) if record.status == KeyStatus::Pending => pending.push((path, record)),
+            Ok(_) => {}
+            Err(e) => println!("{} Skipping unreadable key record {}: {}", "[WARN]".yellow(), path.display(), e),
+        }
+    }
+    if pending.is_empty() {
+        println!("{} No keys with an unknown status in {}", "[INFO]".blue(), dir.display());
+        return;
+    }
+    pending.sort_by_key(|(_, record)| record.found_at);
+
+    let now = unix_millis();
+    let outbox = Outbox::new(data_dir);
+    println!("{} {} keys with an unknown status:", "[INFO]".blue(), pending.len());
+    for (path, record) in pending {
+        // Still in the outbox means the next run submits it again
+        let queued = outbox.contains(&record.hash);
+        println!(
+            "\n{} {}\n  seed: {}\n  public key: {}\n  found {}s ago, {}",
+            "[KEY]".green(),
+            path.display(),
+            record.seed,
+            record.public_key,
+            now.saturating_sub(record.found_at) / 1000,
+            if queued { "still queued for submission" } else { "not queued, check the coin with the daemon" }
+        );
+    }
+}
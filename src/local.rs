@@ -0,0 +1,198 @@
+use colored::*;
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use secp256k1::ecdsa::Signature;
+use secp256k1::{All, PublicKey, Secp256k1};
+use tokio::sync::Notify;
+use tokio::time;
+
+use crate::get_job::{Job, JobError};
+use crate::hash256::{Hash256, Target};
+use crate::job_source::JobSource;
+use crate::submit::SubmitOutcome;
+use crate::util::unix_millis;
+use crate::verify::{self, VerifyError};
+
+struct Challenge {
+    seed: String,
+    last_found: u64,
+}
+
+/// Built-in replacement for the daemon used by `--local`. It generates
+/// challenges, checks solutions exactly like the daemon and counts them,
+/// without touching the network.
+pub struct LocalDaemon {
+    challenge: Mutex<Challenge>,
+    diff: Target,
+    reward: f64,
+    rotate_every: Duration,
+    // Wakes the job source when a solve replaced the challenge
+    rotated: Notify,
+    accepted: AtomicU64,
+    rejected: AtomicU64,
+}
+
+impl LocalDaemon {
+    pub fn new(diff: Target, reward: f64, rotate_every: Duration) -> Self {
+        LocalDaemon {
+            challenge: Mutex::new(Challenge { seed: new_seed(), last_found: unix_millis() }),
+            diff,
+            reward,
+            rotate_every,
+            rotated: Notify::new(),
+            accepted: AtomicU64::new(0),
+            rejected: AtomicU64::new(0),
+        }
+    }
+
+    fn job(&self) -> Job {
+        let challenge = self.challenge.lock().unwrap();
+        Job {
+            seed: challenge.seed.clone(),
+            diff: self.diff,
+            reward: self.reward,
+            last_found: challenge.last_found,
+            clock: None,
+        }
+    }
+
+    fn rotate(&self) {
+        let mut challenge = self.challenge.lock().unwrap();
+        challenge.seed = new_seed();
+        challenge.last_found = unix_millis();
+    }
+
+    /// Takes a solution like `/challenge-solved` would. An accepted one
+    /// starts a new challenge.
+    pub fn accept(&self, secp: &Secp256k1<All>, public_key: &PublicKey, signature: &Signature, hash: &Hash256, seed: &str) -> SubmitOutcome {
+        let result = {
+            let challenge = self.challenge.lock().unwrap();
+            if challenge.seed != seed {
+                // Another solve replaced it first, the daemon rejects these too
+                println!("{} Local challenge already replaced, solution dropped\n", "[WARN]".yellow());
+                return SubmitOutcome::StaleSeed;
+            }
+            verify::verify_solution(secp, public_key, signature, hash, seed, &self.diff)
+        };
+        match result {
+            Ok(()) => {
+                let accepted = self.accepted.fetch_add(1, Ordering::Relaxed) + 1;
+                println!("{} {} ({} accepted, {} rejected)\n", "[INFO]".blue(), "Accepted locally.".green(), accepted, self.rejected.load(Ordering::Relaxed));
+                self.rotate();
+                self.rotated.notify_one();
+                SubmitOutcome::Accepted(None)
+            }
+            Err(e) => {
+                let rejected = self.rejected.fetch_add(1, Ordering::Relaxed) + 1;
+                println!("{} Local solution rejected: {} ({} accepted, {} rejected)\n", "[ERROR]".red(), e, self.accepted.load(Ordering::Relaxed), rejected);
+                match e {
+                    VerifyError::InvalidSignature => SubmitOutcome::InvalidSignature,
+                    VerifyError::HashMismatch { .. } | VerifyError::AboveTarget => SubmitOutcome::InvalidHash,
+                }
+            }
+        }
+    }
+}
+
+/// Hands out the local daemon's challenges, a new one on every solve and
+/// otherwise every `rotate_every`.
+pub struct LocalJobSource {
+    daemon: Arc<LocalDaemon>,
+    started: bool,
+}
+
+impl LocalJobSource {
+    pub fn new(daemon: Arc<LocalDaemon>) -> Self {
+        LocalJobSource { daemon, started: false }
+    }
+}
+
+impl JobSource for LocalJobSource {
+    async fn next_job(&mut self) -> Result<Job, JobError> {
+        if self.started {
+            tokio::select! {
+                _ = self.daemon.rotated.notified() => {}
+                _ = time::sleep(self.daemon.rotate_every) => self.daemon.rotate(),
+            }
+        }
+        self.started = true;
+        Ok(self.daemon.job())
+    }
+}
+
+fn new_seed() -> String {
+    hex::encode(rand::random::<[u8; 16]>())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use secp256k1::SecretKey;
+
+    // Signed solution for `seed` by the key [byte; 32]
+    fn solution(secp: &Secp256k1<All>, byte: u8, seed: &str) -> (PublicKey, Signature, Hash256) {
+        let private_key = SecretKey::from_byte_array(&[byte; 32]).unwrap();
+        let public_key = PublicKey::from_secret_key(secp, &private_key);
+        let signature = secp.sign_ecdsa(&verify::holder_message(&public_key), &private_key);
+        (public_key, signature, verify::solution_hash(&public_key, seed))
+    }
+
+    fn seed(daemon: &LocalDaemon) -> String {
+        daemon.challenge.lock().unwrap().seed.clone()
+    }
+
+    #[test]
+    fn broken_solutions_are_rejected() {
+        let secp = Secp256k1::new();
+        let daemon = LocalDaemon::new(Target::MAX, 1.0, Duration::from_secs(3600));
+        let seed = seed(&daemon);
+        let (public_key, signature, hash) = solution(&secp, 1, &seed);
+        let (_, other_signature, other_hash) = solution(&secp, 2, &seed);
+
+        assert_eq!(daemon.accept(&secp, &public_key, &other_signature, &hash, &seed), SubmitOutcome::InvalidSignature);
+        assert_eq!(daemon.accept(&secp, &public_key, &signature, &other_hash, &seed), SubmitOutcome::InvalidHash);
+        assert_eq!(daemon.accept(&secp, &public_key, &signature, &hash, "old seed"), SubmitOutcome::StaleSeed);
+
+        let hard = LocalDaemon::new(Target::ZERO, 1.0, Duration::from_secs(3600));
+        let hard_seed = self::seed(&hard);
+        let (public_key, signature, hash) = solution(&secp, 1, &hard_seed);
+        assert_eq!(hard.accept(&secp, &public_key, &signature, &hash, &hard_seed), SubmitOutcome::InvalidHash);
+
+        // None of these end the challenge
+        assert_eq!(self::seed(&daemon), seed);
+        assert_eq!(daemon.accepted.load(Ordering::Relaxed), 0);
+        assert_eq!(daemon.rejected.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn valid_solutions_are_accepted_and_rotate_the_challenge() {
+        let secp = Secp256k1::new();
+        let daemon = LocalDaemon::new(Target::MAX, 1.0, Duration::from_secs(3600));
+        let seed = seed(&daemon);
+        let (public_key, signature, hash) = solution(&secp, 1, &seed);
+
+        assert_eq!(daemon.accept(&secp, &public_key, &signature, &hash, &seed), SubmitOutcome::Accepted(None));
+        assert_eq!(daemon.accepted.load(Ordering::Relaxed), 1);
+        assert_ne!(self::seed(&daemon), seed);
+        // The same solution is late now
+        assert_eq!(daemon.accept(&secp, &public_key, &signature, &hash, &seed), SubmitOutcome::StaleSeed);
+    }
+
+    #[tokio::test]
+    async fn job_source_hands_out_the_next_challenge_after_a_solve() {
+        let secp = Secp256k1::new();
+        let daemon = Arc::new(LocalDaemon::new(Target::MAX, 2.0, Duration::from_secs(3600)));
+        let mut source = LocalJobSource::new(Arc::clone(&daemon));
+        let first = source.next_job().await.unwrap();
+        assert_eq!((first.seed.as_str(), first.diff, first.reward), (seed(&daemon).as_str(), Target::MAX, 2.0));
+
+        let (public_key, signature, hash) = solution(&secp, 1, &first.seed);
+        daemon.accept(&secp, &public_key, &signature, &hash, &first.seed);
+        // Well before the hour between rotations
+        let next = time::timeout(Duration::from_secs(5), source.next_job()).await.unwrap().unwrap();
+        assert_ne!(next.seed, first.seed);
+        assert_eq!(next.seed, seed(&daemon));
+    }
+}
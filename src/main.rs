@@ -1,14 +1,12 @@
 use colored::*;
 
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::time::{Duration, Instant};
 use tokio::time;
-use std::sync::Arc;
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
 
 use secp256k1::Secp256k1;
-use secp256k1::rand::rngs::OsRng;
-use secp256k1::hashes::{sha256, Hash};
-use hex::encode;
-use num_bigint::BigUint;
 
 use std::io::Write;
 use crossterm::terminal::size;
@@ -18,25 +16,57 @@ mod get_job;
 mod submit;
 mod report;
 mod gpu;
-use submit::Solution;
+mod endomorphism;
+mod cpu;
+mod stats;
+mod hasher;
+mod sched;
+mod cli;
+mod rng;
+mod bench;
+mod hash256;
+mod backoff;
+mod failover;
+mod job_source;
+mod journal;
+mod verify;
+mod local;
+mod job_timing;
+mod outbox;
+mod keys;
+mod util;
+use submit::{Solution, SubmitContext, SubmitOutcome};
 use config::Reporting;
-use get_job::Job;
+use get_job::{JobPublisher, JobSnapshot, JobState};
+use backoff::Backoff;
+use failover::ServerList;
+use job_source::{JobHandoff, JobSource, PollingJobSource, SseJobSource};
+use job_timing::JobClock;
+use outbox::Outbox;
+use journal::JobJournal;
+use local::{LocalDaemon, LocalJobSource};
+use hash256::Target;
 use gpu::GPUMiningPool;
-
-pub fn pad_start_256_bit_int(value: &BigUint) -> String {
-    let mut hex_string = value.to_str_radix(16); // Convert to hex
-    // Ensure the string is 64 characters long (256 bits)
-    let padding = 64 - hex_string.len();
-    if padding > 0 {
-        hex_string = format!("{}{}", "0".repeat(padding), hex_string);
-    }
-
-    hex_string
-}
+use cpu::{CpuEvent, CpuMinerOptions, CpuMiningPool};
+use stats::MinerStats;
+use hasher::{Backend, BatchHasher};
+use sched::{SchedPolicy, ThreadPolicy};
+use clap::Parser;
 
 
 #[tokio::main]
 async fn main() {
+    let cli = cli::Cli::parse();
+    if let Some(seed) = cli.deterministic_seed {
+        // Only bench and --local keep the predictable keys away from real coins
+        if cli.command.is_none() && !cli.local && !cli.allow_deterministic_on_network {
+            println!("{} --deterministic-seed mines coins whose keys anyone can derive from the seed, use it with --local or bench", "[ERROR]".red());
+            println!("{} Pass --allow-deterministic-on-network to mine against the daemon with it anyway", "[ERROR]".red());
+            return;
+        }
+        println!("{} Deterministic mode with seed {}: private keys are predictable, do not mine real coins with it!", "[WARN]".yellow(), seed);
+    }
+
     let config = match config::load() {
         Ok(config) => Arc::new(tokio::sync::RwLock::new(config)),
         Err(_) => {
@@ -44,24 +74,54 @@ async fn main() {
             Arc::new(tokio::sync::RwLock::new(config::CLCMinerConfig {
                 server: String::from("https://master.centrix.fi"),
                 submit_server: String::from("https://master.centrix.fi"),
+                backup_servers: vec![],
+                backup_submit_servers: vec![],
                 rewards_dir: String::from("./rewards"),
+                data_dir: String::from("./data"),
                 thread: -1,
                 gpu: 0,
                 gpu_platform: String::from("auto"),
                 gpu_workgroup_size: 256,
                 gpu_batch_size: 1048576,
+                endomorphism: false,
+                cpu_hasher: String::from("auto"),
+                cpu_affinity: vec![],
+                cpu_nice: 0,
+                cpu_sched_policy: String::from("normal"),
+                cpu_limit: String::from("100%"),
                 on_mined: String::from(""),
                 report_interval: 10,
                 job_interval: 1,
+                job_source: String::from("poll"),
+                challenge_check_interval: 1,
+                job_retry_max: 60,
+                offline_grace: 120,
                 reporting: Reporting {
                     report_server: String::from(""),
                     report_user: String::from(""),
                 },
                 pool_secret: String::from(""),
+                submit_method: String::from("auto"),
             }))
         }
     };
     
+    match cli.command {
+        Some(cli::Command::Bench { seconds, json }) => {
+            bench::run(&*config.read().await, seconds, json, cli.deterministic_seed).await;
+            return;
+        }
+        Some(cli::Command::Jobs) => {
+            journal::print_summary(&config.read().await.data_dir);
+            return;
+        }
+        Some(cli::Command::Keys) => {
+            keys::print_pending(&config.read().await.data_dir);
+            return;
+        }
+        None => {}
+    }
+
     // Log values if optional settings are specified
     if config.read().await.reporting.report_server != "" {
         println!("{} {}/report", "[INFO] Going to report to:".blue(), config.read().await.reporting.report_server);
@@ -97,276 +157,324 @@ async fn main() {
     };
 
     // Job handling
-    let current_job = Arc::new(tokio::sync::RwLock::new(Job::get_wait_job()));
+    let (job_publisher, job_rx) = JobPublisher::new();
+    let job_publisher = Arc::new(job_publisher);
     
-    // Stats
-    let hash_count = Arc::new(tokio::sync::RwLock::new(0_u64));
-    let calced_hash_count = Arc::new(tokio::sync::RwLock::new(0_f64));
-    let total_mined = Arc::new(tokio::sync::RwLock::new(0_f64));
-    let best: Arc<tokio::sync::RwLock<BigUint>> = Arc::new(tokio::sync::RwLock::new(BigUint::parse_bytes("FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF".as_bytes(), 16).unwrap()));
+    // Threading
+    let (thread_num, thread_policy) = build_thread_policy(&*config.read().await);
+
+    // Stats, one shard per CPU thread plus one for the GPU loop
+    let gpu_shard = thread_num;
+    let stats = Arc::new(MinerStats::new(thread_num + 1));
+
+    // Daemon clock offset, for job ages from its lastFound
+    let job_clock = Arc::new(JobClock::new());
+    // Jobs fetched outside the job source, and requests to check the current one
+    let job_handoff = JobHandoff::new();
 
     // Log data
-    let hash_count_clone = Arc::clone(&hash_count);
-    let calced_hash_count_clone = Arc::clone(&calced_hash_count);
-    let best_clone = Arc::clone(&best);
+    let stats_clone = Arc::clone(&stats);
+    let job_publisher_clone = Arc::clone(&job_publisher);
+    let job_clock_clone = Arc::clone(&job_clock);
+    let job_handoff_clone = job_handoff.clone();
     tokio::spawn(async move {
+        let mut last_hashes: u64 = 0;
+        // Epoch of the last job found stale, so each is only checked once
+        let mut stale_epoch = 0;
         loop {
             time::sleep(Duration::from_secs(3)).await;
             {
-                let mut hash_count_unlocked = hash_count_clone.write().await;
-                let mut calced_hash_count_unlocked = calced_hash_count_clone.write().await;
+                let total_hashes = stats_clone.snapshot().hashes;
+                let hash_count = total_hashes - last_hashes;
+                last_hashes = total_hashes;
                 let rate: f32;
                 let unit: &str;
     
-                if *hash_count_unlocked >= (3 * 1_000_000_000_000_u64) {
-                    rate = (*hash_count_unlocked as f32) / (3.0 * 1e12);
+                if hash_count >= (3 * 1_000_000_000_000_u64) {
+                    rate = (hash_count as f32) / (3.0 * 1e12);
                     unit = "TH/s";
-                } else if *hash_count_unlocked >= (3 * 1_000_000_000) {
-                    rate = (*hash_count_unlocked as f32) / (3.0 * 1e9);
+                } else if hash_count >= (3 * 1_000_000_000) {
+                    rate = (hash_count as f32) / (3.0 * 1e9);
                     unit = "GH/s";
-                } else if *hash_count_unlocked >= (3 * 1_000_000) {
-                    rate = (*hash_count_unlocked as f32) / (3.0 * 1e6);
+                } else if hash_count >= (3 * 1_000_000) {
+                    rate = (hash_count as f32) / (3.0 * 1e6);
                     unit = "M/s";
-                } else if *hash_count_unlocked >= (3 * 1_000) {
-                    rate = (*hash_count_unlocked as f32) / (3.0 * 1e3);
+                } else if hash_count >= (3 * 1_000) {
+                    rate = (hash_count as f32) / (3.0 * 1e3);
                     unit = "KH/s";
                 } else {
-                    rate = *hash_count_unlocked as f32;
+                    rate = hash_count as f32;
                     unit = "H/s";
                 }
     
+                let job = job_publisher_clone.current();
+                let probability = job.job.diff.probability();
+                stats_clone.set_hashrate((hash_count as f64) / (3.0 * 1e3));
+                stats_clone.set_expected_secs(stats::expected_secs(probability, hash_count as f64 / 3.0));
+                stats_clone.take_best();
+                let snapshot = stats_clone.snapshot();
+
+                if job.is_active() && job.epoch != stale_epoch && job_clock_clone.is_stale(&job.job, snapshot.expected_secs) {
+                    stale_epoch = job.epoch;
+                    println!(
+                        "\n{} Challenge {} has been open for {}, far longer than a coin should take, checking it is still current",
+                        "[WARN]".yellow(),
+                        job.job.seed,
+                        stats::format_duration(job_clock_clone.job_age(&job.job).as_secs_f64())
+                    );
+                    job_handoff_clone.refresh();
+                }
+
                 // Replaces the previous printed line
                 let (width, _height) = size().unwrap();
-                let out = format!("\r{} {}{}", "[INFO]".blue(), rate, unit);
-                print!("\r\r{}{}", out, " ".repeat(width as usize - out.len()));
+                // How long the challenge has been open, so unusually long jobs stand out
+                let open_for = match job.state {
+                    JobState::Waiting => String::from("-"),
+                    _ => stats::format_duration(job_clock_clone.job_age(&job.job).as_secs_f64()),
+                };
+                let out = format!(
+                    "\r{} {}{} | coin in ~{} | luck {} | last found {} ago",
+                    "[INFO]".blue(),
+                    rate,
+                    unit,
+                    stats::format_duration(snapshot.expected_secs),
+                    stats::format_luck(&snapshot),
+                    open_for
+                );
+                print!("\r\r{}{}", out, " ".repeat((width as usize).saturating_sub(out.len())));
                 std::io::stdout().flush().unwrap(); // Ensure immediate output
-                
-                *calced_hash_count_unlocked = (*hash_count_unlocked as f64) / (3.0 * 1e3);
-                *hash_count_unlocked = 0;
-                {
-                    let mut best_unlocked = best_clone.write().await;
-                    *best_unlocked = BigUint::parse_bytes("FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF".as_bytes(), 16).unwrap();
-                }
             }
         }
     });
     
 
-    // Update job at interval
-    let current_job_clone = Arc::clone(&current_job);
-    let config_clone = Arc::clone(&config);
-    tokio::spawn(async move {
-        loop {
-            let server_url = config_clone.read().await.server.clone();
-            let job = match get_job::get_job(server_url).await {
-                Ok(job) => job,
-                Err(e) => {
-                    eprintln!("{} {}", "[ERROR] Error fetching job:".red(), e);
-                    continue;
-                }
-            };
-            {
-                let mut job_mut = current_job_clone.write().await;
-                if job_mut.seed != job.seed {
-                    *job_mut = job;
-                    
-                    let duration_since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
-                    let elapsed_secs = duration_since_epoch.as_secs();
-        
-                    println!("\n\n{}", "[INFO] New job".blue());
-                    println!("{} {} {}", "[INFO]".blue(), "seed:", job_mut.seed);
-                    println!("{} {} {}", "[INFO]".blue(), "diff:", pad_start_256_bit_int(&job_mut.diff));
-                    println!("{} {} {}", "[INFO]".blue(), "reward:", job_mut.reward.to_string().green());
-        
-                    let time_since_last_found = elapsed_secs - job_mut.last_found / 1000;
-                    println!("{} {} {}s ago\n\n", "[INFO]".blue(), "Last mined", time_since_last_found);
-                }
+    // Local challenges are made up, they stay out of the journal of real ones
+    let journal = if cli.local { None } else { Some(Arc::new(JobJournal::new(&config.read().await.data_dir))) };
+
+    // --local swaps the daemon for a built-in one, nothing goes over the network
+    let local = if cli.local {
+        let diff = match Target::from_hex(&cli.local_diff) {
+            Ok(diff) => diff,
+            Err(e) => {
+                println!("{} Invalid --local-diff: {}", "[ERROR]".red(), e);
+                return;
             }
-            time::sleep(Duration::from_secs(config_clone.read().await.job_interval as u64)).await;
-        }
-    });
+        };
+        println!("{} Local mode: diff {} ({} leading zero bits), new challenge every {}s", "[INFO]".blue(), diff, diff.leading_zero_bits(), cli.local_rotate);
+        Some(Arc::new(LocalDaemon::new(diff, 1.0, Duration::from_secs(cli.local_rotate.max(1)))))
+    } else {
+        None
+    };
 
-    // Reporting
-    let config_clone = Arc::clone(&config);
-    let calced_hash_count_unlocked = Arc::clone(&calced_hash_count);
-    let total_mined_clone = Arc::clone(&total_mined);
-    let best_clone = Arc::clone(&best);
-    tokio::spawn(async move {
-        loop {
-            let res = report::report(
-                &config_clone.read().await.reporting.report_server,
-                &config_clone.read().await.reporting.report_user,
-                &*calced_hash_count_unlocked.read().await,
-                &*total_mined_clone.read().await,
-                &pad_start_256_bit_int(&*best_clone.read().await)
-            ).await;
-            if res != "" {
-                println!("\n{} Error reporting: {}", "[ERROR]".red(), res);
+    // Job updates, pushed by the daemon or polled at job_interval
+    let job_servers = Arc::new(ServerList::new("job", config.read().await.job_servers()));
+    let poll_interval = Duration::from_secs(config.read().await.job_interval.max(1) as u64);
+    match (&local, config.read().await.job_source.as_str()) {
+        (Some(local), _) => {
+            tokio::spawn(run_job_source(LocalJobSource::new(Arc::clone(local)), Arc::clone(&job_publisher), journal.clone(), Arc::clone(&stats), Arc::clone(&job_clock), Arc::clone(&config)));
+        }
+        (None, "sse") => {
+            tokio::spawn(run_job_source(SseJobSource::new(job_servers, poll_interval, job_handoff.clone()), Arc::clone(&job_publisher), journal.clone(), Arc::clone(&stats), Arc::clone(&job_clock), Arc::clone(&config)));
+        }
+        (None, source) => {
+            if source != "poll" {
+                println!("{} Unknown job_source \"{}\", polling instead", "[WARN]".yellow(), source);
+            }
+            let source = PollingJobSource::new(Arc::clone(&job_servers), poll_interval, job_handoff.clone());
+            // Between slow polls, a lightweight seed check stops work on challenges the network solved
+            let check_interval = Duration::from_secs(config.read().await.challenge_check_interval.max(0) as u64);
+            if !check_interval.is_zero() && check_interval < poll_interval {
+                tokio::spawn(job_source::watch_challenge(job_servers, Arc::clone(&job_publisher), check_interval, job_handoff.clone()));
             }
-            time::sleep(Duration::from_secs(config_clone.read().await.report_interval as u64)).await;
+            tokio::spawn(run_job_source(source, Arc::clone(&job_publisher), journal.clone(), Arc::clone(&stats), Arc::clone(&job_clock), Arc::clone(&config)));
         }
-    });
+    }
+
+    // Reporting, there is no daemon to report to in local mode
+    if local.is_none() {
+        let config_clone = Arc::clone(&config);
+        let stats_clone = Arc::clone(&stats);
+        tokio::spawn(async move {
+            loop {
+                let snapshot = stats_clone.snapshot();
+                let res = report::report(
+                    &config_clone.read().await.reporting.report_server,
+                    &config_clone.read().await.reporting.report_user,
+                    &snapshot.hashrate,
+                    &snapshot.total_mined,
+                    &stats::best_to_hash(snapshot.best).to_string(),
+                    &snapshot.expected_secs,
+                    snapshot.luck()
+                ).await;
+                if !res.is_empty() {
+                    println!("\n{} Error reporting: {}", "[ERROR]".red(), res);
+                }
+                time::sleep(Duration::from_secs(config_clone.read().await.report_interval as u64)).await;
+            }
+        });
+    }
 
-    // Threading
-    let thread_num: usize = if config.read().await.thread == -1 { std::thread::available_parallelism().unwrap().get() } else { config.read().await.thread as usize };
     println!("{} Using {} CPU threads", "[INFO]".blue(), thread_num.to_string().green());
+    if config.read().await.endomorphism {
+        println!("{} Endomorphism search enabled, testing {} pubkeys per point", "[INFO]".blue(), endomorphism::CANDIDATES_PER_POINT);
+    }
     let mut handles = vec![];
+
+    // Shared by every miner so health learned by one submission helps the next
+    let submit_context = {
+        let config = config.read().await;
+        if !["auto", "post", "get"].contains(&config.submit_method.as_str()) {
+            println!("{} Unknown submit_method \"{}\", using auto", "[WARN]".yellow(), config.submit_method);
+        }
+        Arc::new(SubmitContext {
+            servers: Arc::new(ServerList::new("submit", config.submit_servers())),
+            journal: journal.clone(),
+            outbox: Arc::new(Outbox::new(&config.data_dir)),
+            local: local.clone(),
+            rewards_dir: config.rewards_dir.clone(),
+            on_mined: config.on_mined.clone(),
+            pool_secret: config.pool_secret.clone(),
+            submit_method: config.submit_method.clone(),
+            get_only: Mutex::new(HashSet::new()),
+        })
+    };
+
+    // Solutions an earlier run got no definitive answer for, kept for a networked run in local mode
+    if local.is_none() {
+        let pending = submit_context.outbox.pending();
+        if !pending.is_empty() {
+            println!("{} {} solutions left in the outbox, resubmitting them", "[INFO]".blue(), pending.len());
+        }
+        for pending in pending {
+            match Solution::from_pending(&pending, &submit_context) {
+                Ok(solution) => {
+                    tokio::spawn(solution.resume(Arc::clone(&stats)));
+                }
+                Err(e) => println!("{} Skipping outbox entry {}: {}", "[WARN]".yellow(), pending.hash, e),
+            }
+        }
+    }
     
-    // GPU Mining in main thread to avoid Send + Sync issues
+    // GPU mining runs on its own host thread so the thread policy applies to it too
     if let Some(_gpu_pool_arc) = gpu_pool.clone() {
-        let current_job_clone = Arc::clone(&current_job);
-        let hash_count_clone = Arc::clone(&hash_count);
+        let mut job_rx_clone = job_rx.clone();
+        let job_publisher_clone = Arc::clone(&job_publisher);
+        let stats_clone = Arc::clone(&stats);
         let config_clone = Arc::clone(&config);
-        let total_mined_clone = Arc::clone(&total_mined);
-        let best_clone = Arc::clone(&best);
-
-        let gpu_handle = tokio::task::spawn(async move {
-            // Create a new GPU pool for this thread
-            let mut local_gpu_pool = match gpu::GPUMiningPool::new(
-                config_clone.read().await.gpu as usize
-            ).await {
-                Ok(pool) => pool,
-                Err(e) => {
-                    println!("{} Failed to create GPU pool: {}", "[GPU]".red(), e);
-                    return;
-                }
-            };
+        let submit_context_clone = Arc::clone(&submit_context);
+
+        let gpu_policy = thread_policy.clone();
+        let deterministic_seed = cli.deterministic_seed;
+        let runtime = tokio::runtime::Handle::current();
+
+        // A dedicated thread, a pooled blocking thread would keep the pinning
+        // and priority for whatever blocking work it runs next
+        let gpu_thread = std::thread::Builder::new().name(String::from("gpu-miner")).spawn(move || {
+            if let Err(e) = gpu_policy.apply(gpu_shard) {
+                println!("{} Failed to apply thread policy to GPU thread: {}", "[WARN]".yellow(), e);
+            }
+            runtime.block_on(async move {
+                // Create a new GPU pool for this thread
+                let mut local_gpu_pool = match gpu::GPUMiningPool::new(
+                    config_clone.read().await.gpu as usize
+                ).await {
+                    Ok(pool) => pool,
+                    Err(e) => {
+                        println!("{} Failed to create GPU pool: {}", "[GPU]".red(), e);
+                        return;
+                    }
+                };
             
-            let mut gpu_nonce_base = 0u64;
+                // Base keys come from the key RNG so runs only repeat in deterministic mode
+                let mut gpu_nonce_base = rng::key_rng(deterministic_seed, gpu_shard as u64).next_u64();
             
-            loop {
-                if current_job_clone.read().await.seed == "wait" {
-                    tokio::time::sleep(Duration::from_millis(100)).await;
-                    continue;
-                }
-                
-                let job = {
-                    let job_guard = current_job_clone.read().await;
-                    Job {
-                        seed: job_guard.seed.clone(),
-                        diff: job_guard.diff.clone(),
-                        reward: job_guard.reward,
-                        last_found: job_guard.last_found,
+                loop {
+                    let snapshot = job_rx_clone.borrow_and_update().clone();
+                    if !snapshot.is_active() {
+                        if job_rx_clone.changed().await.is_err() {
+                            return;
+                        }
+                        continue;
                     }
-                };
-                let batch_size = config_clone.read().await.gpu_batch_size;
+                    let job = &snapshot.job;
                 
-                // GPU mining batch
-                let result = local_gpu_pool.mine_parallel(&job.diff, &job.seed, gpu_nonce_base).await;
+                    let batch_size = config_clone.read().await.gpu_batch_size;
+                    // What a call really hashes, a kernel batch per device
+                    let keys = local_gpu_pool.keys_per_call() as u64;
                 
-                match result {
-                    Ok(Some((secret_key, public_key, hash))) => {
-                        let key_diff = BigUint::from_bytes_be(&hex::decode(&hash[2..]).unwrap_or_default());
-                        
-                        if key_diff < *best_clone.read().await {
-                            let mut best_setter = best_clone.write().await;
-                            *best_setter = key_diff.clone();
-                        }
+                    // GPU mining batch
+                    let batch_start = Instant::now();
+                    let result = local_gpu_pool.mine_parallel(&job.diff, &job.seed, gpu_nonce_base).await;
+                
+                    match result {
+                        Ok(Some((secret_key, public_key, hash))) => {
+                            stats_clone.submit_best(&hash);
                         
-                        if job.diff >= key_diff {
-                            println!("\n\n{} GPU Found {}CLCs!", "[GPU]".green(), job.reward.to_string().green());
-                            let solution = Solution {
-                                public_key: public_key,
-                                private_key: secret_key,
-                                server: config_clone.read().await.submit_server.clone(),
-                                hash: hash,
-                                on_mined: config_clone.read().await.on_mined.clone(),
-                                rewards_dir: config_clone.read().await.rewards_dir.clone(),
-                                reward: job.reward,
-                                pool_secret: config_clone.read().await.pool_secret.clone()
-                            };
+                            if hash.meets(&job.diff) {
+                                println!("\n\n{} GPU Found {}CLCs!", "[GPU]".green(), job.reward.to_string().green());
+                                stats_clone.add_found();
+                                let solution = Solution::new(secret_key, public_key, hash, job, &submit_context_clone);
                             
-                            {
-                                let mut job_setter = current_job_clone.write().await;
-                                *job_setter = job_setter.get_pause_job();
-                            }
+                                job_publisher_clone.pause(snapshot.epoch);
                             
-                            {
                                 let secp = Secp256k1::new();
-                                let mut total_setter = total_mined_clone.write().await;
-                                solution.submit(&secp, &mut total_setter).await;
+                                let outcome = solution.submit(&secp, &stats_clone).await;
+                                resume_if_open(&job_publisher_clone, &snapshot, &outcome);
                             }
-                        }
                         
-                        // Update hash count for GPU work
-                        {
-                            let mut hash_count_setter = hash_count_clone.write().await;
-                            *hash_count_setter += batch_size as u64;
+                            // Update hash count for GPU work
+                            stats_clone.add_hashes(gpu_shard, keys);
+                            stats_clone.add_expected(keys as f64 * job.diff.probability());
+                        }
+                        Ok(None) => {
+                            // No solution found in this batch
+                            stats_clone.add_hashes(gpu_shard, keys);
+                            stats_clone.add_expected(keys as f64 * job.diff.probability());
+                        }
+                        Err(e) => {
+                            println!("{} GPU mining error: {}", "[GPU]".red(), e);
+                            tokio::time::sleep(Duration::from_secs(1)).await;
                         }
                     }
-                    Ok(None) => {
-                        // No solution found in this batch
-                        let mut hash_count_setter = hash_count_clone.write().await;
-                        *hash_count_setter += batch_size as u64;
-                    }
-                    Err(e) => {
-                        println!("{} GPU mining error: {}", "[GPU]".red(), e);
-                        tokio::time::sleep(Duration::from_secs(1)).await;
-                    }
-                }
                 
-                gpu_nonce_base = gpu_nonce_base.wrapping_add(batch_size as u64);
-            }
+                    gpu_nonce_base = gpu_nonce_base.wrapping_add(batch_size as u64);
+                    gpu_policy.throttle(batch_start.elapsed());
+                }
+            });
         });
-        
-        handles.push(gpu_handle);
-        println!("{} GPU mining thread started", "[GPU]".green());
-    }
 
-    for _ in 0..thread_num {
-        let current_job_clone = Arc::clone(&current_job);
-        let hash_count_clone = Arc::clone(&hash_count);
-        let config_clone = Arc::clone(&config);
-        let total_mined_clone = Arc::clone(&total_mined);
-        let best_clone = Arc::clone(&best);
+        match gpu_thread {
+            Ok(gpu_thread) => {
+                handles.push(tokio::task::spawn_blocking(move || gpu_thread.join().unwrap()));
+                println!("{} GPU mining thread started", "[GPU]".green());
+            }
+            Err(e) => println!("{} Failed to start GPU mining thread: {}", "[GPU]".red(), e),
+        }
+    }
 
-        let handle = tokio::spawn(async move {
-            let secp = Secp256k1::new();
-            let mut rate: u64 = 0;
-            loop {
-                if current_job_clone.read().await.seed == "wait" {
-                    continue;
-                }
-                // Actually mining
-                let (secret_key, public_key) = secp.generate_keypair(&mut OsRng);
-                let hashed_public_key = sha256::Hash::hash(format!("{}{}", encode(public_key.serialize_uncompressed()), current_job_clone.read().await.seed).as_bytes());
-                
-                // The difficulty of the key we just created and hashed
-                let key_diff = BigUint::from_bytes_be(&hashed_public_key.to_byte_array()[..]);
-                if key_diff < *best_clone.read().await {
-                    let mut best_setter = best_clone.write().await;
-                    *best_setter = key_diff.clone();
-                }
-                if current_job_clone.read().await.diff >= key_diff {
-                    println!("\n\n{} Found {}CLCs!", "[INFO]".blue(), current_job_clone.read().await.reward.to_string().green());
-                    let solution = Solution {
-                        public_key: public_key,
-                        private_key: secret_key,
-                        server: config_clone.read().await.submit_server.clone(),
-                        hash: hashed_public_key.to_string(),
-                        on_mined: config_clone.read().await.on_mined.clone(),
-                        rewards_dir: config_clone.read().await.rewards_dir.clone(),
-                        reward: current_job_clone.read().await.reward,
-                        pool_secret: config_clone.read().await.pool_secret.clone()
-                    };
-                    {
-                        let mut job_setter = current_job_clone.write().await;
-                        *job_setter = job_setter.get_pause_job();
-                    }
-                    {
-                        let mut total_setter = total_mined_clone.write().await;
-                        solution.submit(&secp, &mut total_setter).await;
-                    }
-                }
-                rate += 1;
-                if rate == 100 {
-                    let mut hash_count_setter = hash_count_clone.write().await;
-                    *hash_count_setter += 100;
-                    rate = 0;
-                }
+    if thread_num > 0 {
+        let backend = select_hasher(&config.read().await.cpu_hasher);
+        match CpuMiningPool::new(thread_num, thread_policy.clone()) {
+            Ok(pool) => {
+                let (events_tx, events_rx) = mpsc::unbounded_channel();
+                let options = CpuMinerOptions {
+                    endomorphism: config.read().await.endomorphism,
+                    backend,
+                    deterministic_seed: cli.deterministic_seed,
+                };
+                pool.start(job_rx.clone(), options, Arc::clone(&stats), events_tx);
+                handles.push(tokio::spawn(handle_cpu_events(
+                    pool,
+                    events_rx,
+                    Arc::clone(&job_publisher),
+                    Arc::clone(&submit_context),
+                    Arc::clone(&stats),
+                )));
             }
-        });
-        handles.push(handle);
+            Err(e) => {
+                println!("{} Failed to start CPU mining threads: {}", "[ERROR]".red(), e);
+            }
+        }
     }
 
     // Await all tasks
@@ -374,3 +482,178 @@ async fn main() {
         handle.await.unwrap();
     }
 }
+
+// Publishes every new job the source hands out. Failures back off with
+// jitter and pause mining once the daemon has been gone for offline_grace.
+async fn run_job_source<S: JobSource>(
+    mut source: S,
+    job_publisher: Arc<JobPublisher>,
+    journal: Option<Arc<JobJournal>>,
+    stats: Arc<MinerStats>,
+    job_clock: Arc<JobClock>,
+    config: Arc<tokio::sync::RwLock<config::CLCMinerConfig>>,
+) {
+    let mut backoff = {
+        let config = config.read().await;
+        Backoff::new(
+            Duration::from_secs(config.job_interval.max(1) as u64),
+            Duration::from_secs(config.job_retry_max.max(1) as u64),
+        )
+    };
+    // Set on the first failed fetch, cleared once a daemon answers again
+    let mut lost_since: Option<Instant> = None;
+    loop {
+        let job = match source.next_job().await {
+            Ok(job) => job,
+            Err(e) if e.is_invalid_job() => {
+                // The daemon is there, the source paces the next attempt
+                println!("\n{} Ignoring invalid job: {}", "[WARN]".yellow(), e);
+                continue;
+            }
+            Err(e) => {
+                let lost = *lost_since.get_or_insert_with(Instant::now);
+                let delay = backoff.next_delay();
+                eprintln!("{} {}", "[ERROR] Error fetching job:".red(), e);
+                println!("{} Connection lost since {}s ago, retrying in {:.1}s", "[WARN]".yellow(), lost.elapsed().as_secs(), delay.as_secs_f64());
+
+                let grace = config.read().await.offline_grace;
+                if grace >= 0 && lost.elapsed() >= Duration::from_secs(grace as u64) && job_publisher.disconnect() {
+                    println!("{} No job server for {}s, pausing mining until it is back", "[WARN]".yellow(), lost.elapsed().as_secs());
+                }
+                time::sleep(delay).await;
+                continue;
+            }
+        };
+        if let Some(lost) = lost_since.take() {
+            println!("{} Connection restored after {}s", "[INFO]".blue(), lost.elapsed().as_secs());
+            backoff.reset();
+        }
+        if let Some(sample) = job.clock {
+            job_clock.observe(sample);
+        }
+        let current = job_publisher.current();
+        if current.job.seed != job.seed {
+            if let Some(journal) = &journal {
+                journal.replace(&job, stats.snapshot().hashes);
+            }
+            // Still mining it, so it wasn't our solve that closed it. A paused
+            // job was either ours or already caught by watch_challenge.
+            if current.is_active() {
+                println!("\n{} Challenge {} was solved by the network, moving on to {}", "[INFO]".blue(), current.job.seed, job.seed);
+            }
+        }
+        if !current.is_active() || current.job.seed != job.seed {
+            let snapshot = job_publisher.publish(job);
+            let job = &snapshot.job;
+
+            println!("\n\n{}", "[INFO] New job".blue());
+            println!("{} seed: {}", "[INFO]".blue(), job.seed);
+            println!("{} diff: {} ({} leading zero bits)", "[INFO]".blue(), job.diff, job.diff.leading_zero_bits());
+            println!("{} reward: {}", "[INFO]".blue(), job.reward.to_string().green());
+
+            let session = stats.snapshot();
+            let hashes_per_sec = session.hashrate * 1e3;
+            println!("{} odds: 1 in {:.0} hashes", "[INFO]".blue(), 1.0 / job.diff.probability());
+            if hashes_per_sec > 0.0 {
+                println!("{} Expected time to a coin: {} at the current hashrate", "[INFO]".blue(), stats::format_duration(stats::expected_secs(job.diff.probability(), hashes_per_sec)));
+            }
+            println!("{} Session luck: {}", "[INFO]".blue(), stats::format_luck(&session));
+
+            let offset_ms = job_clock.offset_ms();
+            if offset_ms.abs() >= 1000 {
+                let direction = if offset_ms > 0 { "ahead of" } else { "behind" };
+                println!("{} Daemon clock is {:.1}s {} ours, job ages are corrected for it", "[INFO]".blue(), offset_ms.abs() as f64 / 1e3, direction);
+            }
+            println!("{} Last mined {}s ago\n\n", "[INFO]".blue(), job_clock.job_age(job).as_secs());
+        }
+    }
+}
+
+// Works out the mining thread count and the placement/priority every mining
+// thread gets from the cpu_* options.
+fn build_thread_policy(config: &config::CLCMinerConfig) -> (usize, ThreadPolicy) {
+    let limit = match sched::parse_cpu_limit(&config.cpu_limit) {
+        Ok(limit) => limit,
+        Err(e) => {
+            println!("{} {}, not limiting CPU usage", "[WARN]".yellow(), e);
+            1.0
+        }
+    };
+    let policy = match SchedPolicy::from_name(&config.cpu_sched_policy) {
+        Some(policy) => policy,
+        None => {
+            println!("{} Unknown cpu_sched_policy \"{}\", using normal", "[WARN]".yellow(), config.cpu_sched_policy);
+            SchedPolicy::Normal
+        }
+    };
+    let (threads, duty_cycle) = sched::plan_threads(config.thread, limit);
+    if duty_cycle < 1.0 {
+        println!("{} CPU limit {}, mining threads run at a {:.0}% duty cycle", "[INFO]".blue(), config.cpu_limit, duty_cycle * 100.0);
+    }
+    if !config.cpu_affinity.is_empty() {
+        println!("{} Pinning mining threads to cores {:?}", "[INFO]".blue(), config.cpu_affinity);
+    }
+    (threads, ThreadPolicy {
+        cores: config.cpu_affinity.clone(),
+        nice: config.cpu_nice,
+        policy,
+        duty_cycle,
+    })
+}
+
+// Picks the configured CPU hashing backend and checks it against the scalar
+// hasher before any real work is done with it.
+fn select_hasher(name: &str) -> Backend {
+    let backend = match Backend::from_name(name) {
+        Some(backend) if backend.is_supported() => backend,
+        Some(backend) => {
+            println!("{} {} hashing is not supported by this CPU, detecting instead", "[WARN]".yellow(), backend);
+            Backend::detect()
+        }
+        None => {
+            println!("{} Unknown cpu_hasher \"{}\", detecting instead", "[WARN]".yellow(), name);
+            Backend::detect()
+        }
+    };
+    if backend != Backend::Scalar && !BatchHasher::new(backend).self_test() {
+        println!("{} {} hasher failed its self-test, falling back to scalar", "[ERROR]".red(), backend);
+        return Backend::Scalar;
+    }
+    println!("{} CPU hasher: {} ({} lanes)", "[INFO]".blue(), backend, backend.lanes());
+    backend
+}
+
+// Submits solutions coming from the CPU worker threads.
+// Owns the pool so the workers live as long as this task.
+async fn handle_cpu_events(
+    _pool: CpuMiningPool,
+    mut events: mpsc::UnboundedReceiver<CpuEvent>,
+    job_publisher: Arc<JobPublisher>,
+    submit_context: Arc<SubmitContext>,
+    stats: Arc<MinerStats>,
+) {
+    let secp = Secp256k1::new();
+    while let Some(event) = events.recv().await {
+        match event {
+            CpuEvent::Solution { private_key, public_key, hash, job } => {
+                if job.epoch != job_publisher.current().epoch {
+                    println!("\n{} Solution is for a replaced job (epoch {}), submitting anyway", "[WARN]".yellow(), job.epoch);
+                }
+                println!("\n\n{} Found {}CLCs!", "[INFO]".blue(), job.job.reward.to_string().green());
+                stats.add_found();
+                let solution = Solution::new(private_key, public_key, hash, &job.job, &submit_context);
+                job_publisher.pause(job.epoch);
+                let outcome = solution.submit(&secp, &stats).await;
+                resume_if_open(&job_publisher, &job, &outcome);
+            }
+        }
+    }
+}
+
+// Mining pauses while a solution is submitted. If the challenge is still open
+// afterwards no new job is coming, so go back to work on this one.
+fn resume_if_open(job_publisher: &JobPublisher, job: &JobSnapshot, outcome: &SubmitOutcome) {
+    if outcome.leaves_challenge_open() && job_publisher.resume(job.epoch) {
+        println!("{} Submission ended with {}, challenge {} is still open, mining it again", "[INFO]".blue(), outcome, job.job.seed);
+    }
+}
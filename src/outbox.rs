@@ -0,0 +1,225 @@
+use colored::*;
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use secp256k1::{PublicKey, SecretKey};
+use serde::{Deserialize, Serialize};
+
+use crate::hash256::{Hash256, Target};
+use crate::keys::{KeyRecord, KeyStatus, KeyStore};
+
+const OUTBOX_DIR: &str = "outbox";
+
+/// A found solution the daemon hasn't given a definitive answer for yet.
+/// Holds everything needed to submit it again after a restart.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PendingSubmission {
+    pub private_key: String,
+    pub public_key: String,
+    pub hash: String,
+    pub seed: String,
+    // Target of the job, missing in entries from before it was kept
+    #[serde(default)]
+    pub diff: Option<String>,
+    pub reward: f64,
+    // Unix milliseconds
+    pub found_at: u64,
+}
+
+impl PendingSubmission {
+    pub fn keys(&self) -> Result<(SecretKey, PublicKey, Hash256), String> {
+        let private_key = SecretKey::from_str(&self.private_key).map_err(|e| format!("bad private key: {}", e))?;
+        let public_key = PublicKey::from_str(&self.public_key).map_err(|e| format!("bad public key: {}", e))?;
+        let hash = Hash256::from_hex(&self.hash)?;
+        Ok((private_key, public_key, hash))
+    }
+
+    /// The job's target, None if the entry doesn't have a readable one.
+    pub fn diff(&self) -> Option<Target> {
+        self.diff.as_deref().and_then(|diff| Target::from_hex(diff).ok())
+    }
+}
+
+/// Solutions waiting to be submitted, one file each in `{data_dir}/outbox`.
+/// A solution is written before its first submission attempt and removed
+/// once the daemon accepted or rejected it, so a timeout, a daemon outage or
+/// a restart doesn't lose a coin. Its key also goes to the key records, which
+/// keep it with the outcome after it left the outbox.
+pub struct Outbox {
+    dir: PathBuf,
+    keys: KeyStore,
+}
+
+impl Outbox {
+    pub fn new(data_dir: &str) -> Self {
+        Outbox { dir: PathBuf::from(data_dir).join(OUTBOX_DIR), keys: KeyStore::new(data_dir) }
+    }
+
+    pub fn add(&self, pending: &PendingSubmission) -> io::Result<()> {
+        self.keys.record(&KeyRecord {
+            private_key: pending.private_key.clone(),
+            public_key: pending.public_key.clone(),
+            hash: pending.hash.clone(),
+            seed: pending.seed.clone(),
+            found_at: pending.found_at,
+            status: KeyStatus::Pending,
+            coin_id: None,
+        })?;
+        fs::create_dir_all(&self.dir)?;
+        write_atomic(&self.path(&pending.hash), serde_json::to_string_pretty(pending).unwrap().as_bytes())
+    }
+
+    pub fn contains(&self, hash: &str) -> bool {
+        self.path(hash).exists()
+    }
+
+    /// Takes the solution out of the outbox once the daemon answered. A
+    /// `Pending` status leaves its key record unresolved for `keys` to list.
+    pub fn finish(&self, hash: &str, status: KeyStatus, coin_id: Option<u64>) {
+        if status != KeyStatus::Pending {
+            self.keys.finalize(hash, status, coin_id);
+        }
+        self.remove(hash);
+    }
+
+    fn remove(&self, hash: &str) {
+        match fs::remove_file(self.path(hash)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                println!("{} Failed to remove {} from the outbox: {}", "[ERROR]".red(), hash, e);
+            }
+            _ => {}
+        }
+    }
+
+    /// Everything left over from earlier runs, oldest first.
+    pub fn pending(&self) -> Vec<PendingSubmission> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(_) => return vec![],
+        };
+        let mut pending = vec![];
+        for path in entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()) {
+            if path.extension().is_none_or(|extension| extension != "json") {
+                continue;
+            }
+            match fs::read_to_string(&path).map_err(|e| e.to_string()).and_then(|text| serde_json::from_str(&text).map_err(|e| e.to_string())) {
+                Ok(submission) => pending.push(submission),
+                Err(e) => println!("{} Skipping unreadable outbox entry {}: {}", "[WARN]".yellow(), path.display(), e),
+            }
+        }
+        pending.sort_by_key(|submission: &PendingSubmission| submission.found_at);
+        pending
+    }
+
+    fn path(&self, hash: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", hash))
+    }
+}
+
+/// Writes `contents` to a temporary file next to `path` and renames it into
+/// place, so a crash never leaves a half written file behind. The file is
+/// only readable by its owner, everything written here holds private keys.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    let temporary = PathBuf::from(temporary);
+    // A leftover from a crash would keep its old permissions
+    match fs::remove_file(&temporary) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    {
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(&temporary)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+    }
+    fs::rename(&temporary, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use secp256k1::Secp256k1;
+    use crate::util::test_dir;
+
+    fn submission(byte: u8, found_at: u64) -> PendingSubmission {
+        let private_key = SecretKey::from_byte_array(&[byte; 32]).unwrap();
+        let public_key = PublicKey::from_secret_key(&Secp256k1::new(), &private_key);
+        PendingSubmission {
+            private_key: private_key.display_secret().to_string(),
+            public_key: public_key.to_string(),
+            hash: hex::encode([byte; 32]),
+            seed: String::from("seed"),
+            diff: Some(hex::encode([0xff; 32])),
+            reward: 1.5,
+            found_at,
+        }
+    }
+
+    fn key_record(data_dir: &str, hash: &str) -> KeyRecord {
+        let text = fs::read_to_string(PathBuf::from(data_dir).join("keys").join(format!("{}.json", hash))).unwrap();
+        serde_json::from_str(&text).unwrap()
+    }
+
+    #[test]
+    fn entries_survive_a_restart() {
+        let dir = test_dir("outbox-round-trip").display().to_string();
+        let outbox = Outbox::new(&dir);
+        let (newer, older) = (submission(2, 2_000), submission(1, 1_000));
+        outbox.add(&newer).unwrap();
+        outbox.add(&older).unwrap();
+        assert!(outbox.contains(&older.hash));
+
+        let pending = Outbox::new(&dir).pending();
+        assert_eq!(pending.iter().map(|p| p.found_at).collect::<Vec<_>>(), vec![1_000, 2_000]);
+        let read = &pending[0];
+        assert_eq!((&read.private_key, &read.public_key, &read.hash), (&older.private_key, &older.public_key, &older.hash));
+        assert_eq!((&read.seed, &read.diff, read.reward), (&older.seed, &older.diff, older.reward));
+        let (private_key, public_key, hash) = read.keys().unwrap();
+        assert_eq!(PublicKey::from_secret_key(&Secp256k1::new(), &private_key), public_key);
+        assert_eq!(hash.to_string(), older.hash);
+        assert_eq!(read.diff(), Some(Target::MAX));
+
+        let record = key_record(&dir, &older.hash);
+        assert_eq!((record.status, record.coin_id, record.found_at), (KeyStatus::Pending, None, 1_000));
+        assert_eq!(record.private_key, older.private_key);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn accepted_entries_leave_the_outbox() {
+        let dir = test_dir("outbox-accepted").display().to_string();
+        let outbox = Outbox::new(&dir);
+        let pending = submission(3, 1_000);
+        outbox.add(&pending).unwrap();
+        outbox.finish(&pending.hash, KeyStatus::Mined, Some(42));
+
+        assert!(!outbox.contains(&pending.hash));
+        assert!(outbox.pending().is_empty());
+        let record = key_record(&dir, &pending.hash);
+        assert_eq!((record.status, record.coin_id), (KeyStatus::Mined, Some(42)));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn unanswered_entries_keep_a_pending_key() {
+        let dir = test_dir("outbox-unanswered").display().to_string();
+        let outbox = Outbox::new(&dir);
+        let pending = submission(4, 1_000);
+        outbox.add(&pending).unwrap();
+        outbox.finish(&pending.hash, KeyStatus::Pending, None);
+
+        assert!(outbox.pending().is_empty());
+        assert_eq!(key_record(&dir, &pending.hash).status, KeyStatus::Pending);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,8 +1,15 @@
-pub async fn report(server: &str, user: &str, speed: &f64, total_mined: &f64, best: &str) -> String {
-    if server == "" {
+pub async fn report(server: &str, user: &str, speed: &f64, total_mined: &f64, best: &str, expected_secs: &f64, luck: Option<f64>) -> String {
+    if server.is_empty() {
         return String::from("");
     }
-    let url = format!("{}/report?user={}&speed={}&best={}&mined={}", server, user, speed, best, total_mined);
+    let mut url = format!(
+        "{}/report?user={}&speed={}&best={}&mined={}&expected_time={}",
+        server, user, speed, best, total_mined, expected_secs
+    );
+    // Left out until enough work was done to tell
+    if let Some(luck) = luck {
+        url.push_str(&format!("&luck={}", luck));
+    }
     match reqwest::get(&url).await {
         Ok(_) => String::new(),
         Err(e) => e.to_string(),
@@ -0,0 +1,45 @@
+use rand_chacha::ChaCha20Rng;
+use secp256k1::rand::rngs::OsRng;
+use secp256k1::rand::{RngCore, SeedableRng};
+
+/// RNG that private keys are drawn from. Normally the OS RNG; with a
+/// deterministic seed every worker gets its own ChaCha20 stream of that seed,
+/// so each thread produces the same keys on every run.
+pub fn key_rng(deterministic_seed: Option<u64>, stream: u64) -> Box<dyn RngCore + Send> {
+    match deterministic_seed {
+        Some(seed) => {
+            let mut rng = ChaCha20Rng::seed_from_u64(seed);
+            rng.set_stream(stream);
+            Box::new(rng)
+        }
+        None => Box::new(OsRng),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use secp256k1::{PublicKey, Secp256k1, SecretKey};
+
+    fn keypairs(deterministic_seed: Option<u64>, stream: u64) -> Vec<(SecretKey, PublicKey)> {
+        let secp = Secp256k1::new();
+        let mut rng = key_rng(deterministic_seed, stream);
+        (0..16).map(|_| secp.generate_keypair(&mut rng)).collect()
+    }
+
+    #[test]
+    fn same_seed_and_stream_give_the_same_keys() {
+        for stream in [0, 1, 7] {
+            assert_eq!(keypairs(Some(42), stream), keypairs(Some(42), stream));
+        }
+    }
+
+    #[test]
+    fn streams_and_seeds_give_different_keys() {
+        let keys = keypairs(Some(42), 0);
+        assert_ne!(keys, keypairs(Some(42), 1), "workers share a stream");
+        assert_ne!(keys, keypairs(Some(43), 0), "seeds share a stream");
+        // No seed means OS randomness, never the same twice
+        assert_ne!(keypairs(None, 0), keypairs(None, 0));
+    }
+}
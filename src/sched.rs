@@ -0,0 +1,208 @@
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchedPolicy {
+    Normal,
+    // Throughput oriented, the kernel assumes the thread is CPU bound
+    Batch,
+    // Only runs when nothing else wants the CPU
+    Idle,
+}
+
+impl SchedPolicy {
+    pub fn from_name(name: &str) -> Option<SchedPolicy> {
+        match name {
+            "normal" => Some(SchedPolicy::Normal),
+            "batch" => Some(SchedPolicy::Batch),
+            "idle" => Some(SchedPolicy::Idle),
+            _ => None,
+        }
+    }
+}
+
+/// Placement and priority applied to every mining thread, CPU workers and the
+/// GPU host thread alike.
+#[derive(Debug, Clone)]
+pub struct ThreadPolicy {
+    // Cores to pin to, worker `i` gets `cores[i % cores.len()]`. Empty means no pinning.
+    pub cores: Vec<usize>,
+    // 0 leaves the priority alone
+    pub nice: i32,
+    pub policy: SchedPolicy,
+    // Fraction of the time a thread may be busy, 1.0 means no throttling
+    pub duty_cycle: f64,
+}
+
+impl ThreadPolicy {
+    /// Applies pinning, nice value and scheduling policy to the calling thread.
+    pub fn apply(&self, worker_index: usize) -> Result<(), String> {
+        if !self.cores.is_empty() {
+            pin_current_thread(self.cores[worker_index % self.cores.len()])?;
+        }
+        if self.policy != SchedPolicy::Normal {
+            set_current_policy(self.policy)?;
+        }
+        if self.nice != 0 {
+            set_current_nice(self.nice)?;
+        }
+        Ok(())
+    }
+
+    /// Sleeps long enough after `busy` of work to keep the thread at its duty cycle.
+    pub fn throttle(&self, busy: Duration) {
+        if self.duty_cycle < 1.0 {
+            std::thread::sleep(busy.mul_f64((1.0 - self.duty_cycle) / self.duty_cycle));
+        }
+    }
+}
+
+/// Parses a `cpu_limit` such as "75%" or "0.75" into a fraction of the machine.
+pub fn parse_cpu_limit(limit: &str) -> Result<f64, String> {
+    let limit = limit.trim();
+    let fraction = match limit.strip_suffix('%') {
+        Some(percent) => percent.trim().parse::<f64>().map(|percent| percent / 100.0),
+        None => limit.parse::<f64>(),
+    }
+    .map_err(|_| format!("invalid cpu_limit \"{}\"", limit))?;
+    if !(fraction > 0.0 && fraction <= 1.0) {
+        return Err(format!("cpu_limit \"{}\" must be above 0% and at most 100%", limit));
+    }
+    Ok(fraction)
+}
+
+/// Turns the configured thread count and CPU limit into the number of worker
+/// threads and the duty cycle each of them runs at. `available_parallelism`
+/// already honors cgroup CPU quotas, so containers get a sensible default.
+pub fn plan_threads(configured: i64, limit: f64) -> (usize, f64) {
+    let available = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    let budget = available as f64 * limit;
+    let threads = if configured == -1 {
+        (budget.floor() as usize).max(1)
+    } else {
+        configured as usize
+    };
+    // Without a limit, extra threads share the cores the way they always did
+    if threads == 0 || limit >= 1.0 {
+        return (threads, 1.0);
+    }
+    (threads, (budget / threads as f64).min(1.0))
+}
+
+#[cfg(target_os = "linux")]
+fn pin_current_thread(core: usize) -> Result<(), String> {
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_SET(core, &mut set);
+        if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+            return Err(format!("failed to pin thread to core {}: {}", core, std::io::Error::last_os_error()));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn set_current_policy(policy: SchedPolicy) -> Result<(), String> {
+    let policy = match policy {
+        SchedPolicy::Normal => libc::SCHED_OTHER,
+        SchedPolicy::Batch => libc::SCHED_BATCH,
+        SchedPolicy::Idle => libc::SCHED_IDLE,
+    };
+    let param = libc::sched_param { sched_priority: 0 };
+    if unsafe { libc::sched_setscheduler(0, policy, &param) } != 0 {
+        return Err(format!("failed to set scheduling policy: {}", std::io::Error::last_os_error()));
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn set_current_nice(nice: i32) -> Result<(), String> {
+    // On Linux the nice value is per thread when addressed by thread id
+    let tid = unsafe { libc::gettid() };
+    if unsafe { libc::setpriority(libc::PRIO_PROCESS, tid as libc::id_t, nice) } != 0 {
+        return Err(format!("failed to set nice {}: {}", nice, std::io::Error::last_os_error()));
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn pin_current_thread(_core: usize) -> Result<(), String> {
+    Err(String::from("cpu_affinity is only supported on Linux"))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_current_policy(_policy: SchedPolicy) -> Result<(), String> {
+    Err(String::from("cpu_sched_policy is only supported on Linux"))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_current_nice(_nice: i32) -> Result<(), String> {
+    Err(String::from("cpu_nice is only supported on Linux"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn available() -> usize {
+        std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+    }
+
+    #[test]
+    fn parses_percentages_and_fractions() {
+        assert_eq!(parse_cpu_limit("75%"), Ok(0.75));
+        assert_eq!(parse_cpu_limit(" 100 % "), Ok(1.0));
+        assert_eq!(parse_cpu_limit("0.5"), Ok(0.5));
+        assert_eq!(parse_cpu_limit("1"), Ok(1.0));
+    }
+
+    #[test]
+    fn rejects_limits_outside_the_machine() {
+        for limit in ["0", "0%", "150%", "1.5", "-0.5", "NaN"] {
+            let error = parse_cpu_limit(limit).unwrap_err();
+            assert!(error.contains("must be above 0%"), "{:?} gave {:?}", limit, error);
+        }
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        for limit in ["", "%", "half", "75 percent", "0.5.1"] {
+            let error = parse_cpu_limit(limit).unwrap_err();
+            assert!(error.starts_with("invalid cpu_limit"), "{:?} gave {:?}", limit, error);
+        }
+    }
+
+    #[test]
+    fn auto_threads_round_down_and_make_up_the_rest_in_duty_cycle() {
+        let available = available();
+        assert_eq!(plan_threads(-1, 1.0), (available, 1.0));
+        for limit in [0.01, 0.3, 0.5, 0.75, 0.99] {
+            let budget = available as f64 * limit;
+            let (threads, duty_cycle) = plan_threads(-1, limit);
+            assert_eq!(threads, (budget.floor() as usize).max(1), "limit {}", limit);
+            assert!(duty_cycle > 0.0 && duty_cycle <= 1.0);
+            // Threads times duty cycle is the budget, under one thread gets one thread
+            assert!((threads as f64 * duty_cycle - budget).abs() < 1e-9, "limit {}", limit);
+        }
+    }
+
+    #[test]
+    fn configured_threads_share_the_budget() {
+        let available = available();
+        assert_eq!(plan_threads(0, 0.5), (0, 1.0));
+        assert_eq!(plan_threads(available as i64, 1.0), (available, 1.0));
+        assert_eq!(plan_threads(available as i64 * 2, 0.5), (available * 2, 0.25));
+        // Fewer threads than the budget run flat out
+        assert_eq!(plan_threads(1, 1.0), (1, 1.0));
+        let (threads, duty_cycle) = plan_threads(available as i64 * 4, 0.5);
+        assert_eq!(threads, available * 4);
+        assert!((duty_cycle - 0.125).abs() < 1e-12);
+    }
+
+    #[test]
+    fn no_limit_never_throttles_extra_threads() {
+        let available = available();
+        for threads in [available + 1, available * 2, available * 16] {
+            assert_eq!(plan_threads(threads as i64, 1.0), (threads, 1.0));
+        }
+    }
+}
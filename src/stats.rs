@@ -0,0 +1,252 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use portable_atomic::AtomicU128;
+
+use crate::hash256::Hash256;
+use crate::submit::SubmitOutcome;
+
+// Keeps every worker's counter on its own cache line so threads never contend
+#[repr(align(64))]
+struct Shard(AtomicU64);
+
+/// Shared miner counters. Workers only ever touch their own shard and do a
+/// lock-free min on `best`, so reading stats never blocks mining.
+pub struct MinerStats {
+    shards: Box<[Shard]>,
+    // Leading 128 bits of the lowest hash seen since the last reset
+    best: AtomicU128,
+    // f64 bits
+    total_mined: AtomicU64,
+    // f64 bits, KH/s as last computed by the stats printer
+    hashrate: AtomicU64,
+    // Hashes that met the job target, whether or not the daemon accepted them
+    found: AtomicU64,
+    // f64 bits, solutions we should have found so far given each job's diff
+    expected: AtomicU64,
+    // f64 bits, seconds to a coin for the current job at the last hashrate
+    expected_secs: AtomicU64,
+    // Submission answers by SubmitOutcome::kind
+    outcomes: [AtomicU64; SubmitOutcome::KINDS],
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct StatsSnapshot {
+    pub hashes: u64,
+    pub best: u128,
+    pub total_mined: f64,
+    pub hashrate: f64,
+    pub found: u64,
+    pub expected: f64,
+    pub expected_secs: f64,
+    pub outcomes: [u64; SubmitOutcome::KINDS],
+}
+
+impl MinerStats {
+    pub fn new(shards: usize) -> Self {
+        MinerStats {
+            shards: (0..shards).map(|_| Shard(AtomicU64::new(0))).collect(),
+            best: AtomicU128::new(u128::MAX),
+            total_mined: AtomicU64::new(0_f64.to_bits()),
+            hashrate: AtomicU64::new(0_f64.to_bits()),
+            found: AtomicU64::new(0),
+            expected: AtomicU64::new(0_f64.to_bits()),
+            expected_secs: AtomicU64::new(f64::INFINITY.to_bits()),
+            outcomes: std::array::from_fn(|_| AtomicU64::new(0)),
+        }
+    }
+
+    pub fn add_hashes(&self, shard: usize, hashes: u64) {
+        self.shards[shard].0.fetch_add(hashes, Ordering::Relaxed);
+    }
+
+    /// Total hashes since start, summed over all shards.
+    fn total_hashes(&self) -> u64 {
+        self.shards.iter().map(|shard| shard.0.load(Ordering::Relaxed)).sum()
+    }
+
+    pub fn submit_best(&self, hash: &Hash256) {
+        let leading = u128::from_be_bytes(hash.0[..16].try_into().unwrap());
+        self.best.fetch_min(leading, Ordering::Relaxed);
+    }
+
+    /// Returns the best hash so far and starts a new window.
+    pub fn take_best(&self) -> u128 {
+        self.best.swap(u128::MAX, Ordering::Relaxed)
+    }
+
+    pub fn add_mined(&self, reward: f64) {
+        let _ = self.total_mined.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+            Some((f64::from_bits(bits) + reward).to_bits())
+        });
+    }
+
+    pub fn set_hashrate(&self, hashrate: f64) {
+        self.hashrate.store(hashrate.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn add_found(&self) {
+        self.found.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Credits work worth `expected` solutions: the hashes done against a job
+    /// times the probability of each meeting its target.
+    pub fn add_expected(&self, expected: f64) {
+        let _ = self.expected.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+            Some((f64::from_bits(bits) + expected).to_bits())
+        });
+    }
+
+    pub fn set_expected_secs(&self, secs: f64) {
+        self.expected_secs.store(secs.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn count_outcome(&self, outcome: &SubmitOutcome) {
+        self.outcomes[outcome.kind()].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> StatsSnapshot {
+        StatsSnapshot {
+            hashes: self.total_hashes(),
+            best: self.best.load(Ordering::Relaxed),
+            total_mined: f64::from_bits(self.total_mined.load(Ordering::Relaxed)),
+            hashrate: f64::from_bits(self.hashrate.load(Ordering::Relaxed)),
+            found: self.found.load(Ordering::Relaxed),
+            expected: f64::from_bits(self.expected.load(Ordering::Relaxed)),
+            expected_secs: f64::from_bits(self.expected_secs.load(Ordering::Relaxed)),
+            outcomes: std::array::from_fn(|kind| self.outcomes[kind].load(Ordering::Relaxed)),
+        }
+    }
+}
+
+impl StatsSnapshot {
+    /// Found solutions over expected ones, 1.0 is average luck. None until
+    /// enough hashes were done to expect anything.
+    pub fn luck(&self) -> Option<f64> {
+        if self.expected > 0.0 {
+            Some(self.found as f64 / self.expected)
+        } else {
+            None
+        }
+    }
+}
+
+/// Expected seconds until a hash meets a target hit with `probability`, at `hashes_per_sec`.
+pub fn expected_secs(probability: f64, hashes_per_sec: f64) -> f64 {
+    1.0 / (probability * hashes_per_sec)
+}
+
+/// Formats seconds with the two largest units, like "3h 12m".
+pub fn format_duration(secs: f64) -> String {
+    if !secs.is_finite() {
+        return String::from("never");
+    }
+    let secs = secs.round() as u64;
+    let (days, hours, minutes, seconds) = (secs / 86400, secs / 3600 % 24, secs / 60 % 60, secs % 60);
+    if days > 0 {
+        format!("{}d {}h", days, hours)
+    } else if hours > 0 {
+        format!("{}h {}m", hours, minutes)
+    } else if minutes > 0 {
+        format!("{}m {}s", minutes, seconds)
+    } else {
+        format!("{}s", seconds)
+    }
+}
+
+/// Luck as shown in the status line and job log.
+pub fn format_luck(snapshot: &StatsSnapshot) -> String {
+    match snapshot.luck() {
+        Some(luck) => format!("{:.0}% ({} found, {:.2} expected)", luck * 100.0, snapshot.found, snapshot.expected),
+        None => format!("- ({} found)", snapshot.found),
+    }
+}
+
+/// Widens the leading 128 bits of a best hash back to a full hash.
+pub fn best_to_hash(best: u128) -> Hash256 {
+    let mut bytes = [0u8; 32];
+    bytes[..16].copy_from_slice(&best.to_be_bytes());
+    Hash256(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash256::Target;
+
+    fn snapshot(found: u64, expected: f64) -> StatsSnapshot {
+        let stats = MinerStats::new(1);
+        for _ in 0..found {
+            stats.add_found();
+        }
+        stats.add_expected(expected);
+        stats.snapshot()
+    }
+
+    #[test]
+    fn luck_is_unknown_until_solutions_are_expected() {
+        let nothing = snapshot(0, 0.0);
+        assert_eq!(nothing.luck(), None);
+        assert_eq!(format_luck(&nothing), "- (0 found)");
+        // Even a lucky find before any work was credited
+        assert_eq!(snapshot(1, 0.0).luck(), None);
+    }
+
+    #[test]
+    fn luck_is_found_over_expected() {
+        assert_eq!(snapshot(0, 0.5).luck(), Some(0.0));
+        assert_eq!(snapshot(1, 1.0).luck(), Some(1.0));
+        assert_eq!(snapshot(3, 2.0).luck(), Some(1.5));
+        assert_eq!(format_luck(&snapshot(1, 4.0)), "25% (1 found, 4.00 expected)");
+    }
+
+    #[test]
+    fn expected_solutions_add_up() {
+        let stats = MinerStats::new(2);
+        let diff = Target::from_hex("00ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff").unwrap();
+        stats.add_expected(512.0 * diff.probability());
+        stats.add_expected(256.0 * diff.probability());
+        assert!((stats.snapshot().expected - 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn expected_time_at_a_known_difficulty() {
+        // 16 leading zero bits, one in 65536 hashes
+        let diff = Target::from_hex("0000ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff").unwrap();
+        assert!((expected_secs(diff.probability(), 65536.0) - 1.0).abs() < 1e-9);
+        assert!((expected_secs(diff.probability(), 1024.0) - 64.0).abs() < 1e-9);
+        assert_eq!(format_duration(expected_secs(diff.probability(), 1.0)), "18h 12m");
+        // No hashrate yet
+        assert_eq!(expected_secs(diff.probability(), 0.0), f64::INFINITY);
+        assert_eq!(format_duration(expected_secs(diff.probability(), 0.0)), "never");
+    }
+
+    #[test]
+    fn durations_use_the_two_largest_units() {
+        assert_eq!(format_duration(0.0), "0s");
+        assert_eq!(format_duration(59.4), "59s");
+        assert_eq!(format_duration(59.5), "1m 0s");
+        assert_eq!(format_duration(60.0), "1m 0s");
+        assert_eq!(format_duration(3599.0), "59m 59s");
+        assert_eq!(format_duration(3600.0), "1h 0m");
+        assert_eq!(format_duration(86399.0), "23h 59m");
+        assert_eq!(format_duration(86400.0), "1d 0h");
+        assert_eq!(format_duration(90061.0), "1d 1h");
+        assert_eq!(format_duration(f64::INFINITY), "never");
+        assert_eq!(format_duration(f64::NAN), "never");
+    }
+
+    #[test]
+    fn best_hash_keeps_the_leading_bits() {
+        let stats = MinerStats::new(1);
+        let mut low = [0xff; 32];
+        low[0] = 0x00;
+        low[1] = 0x12;
+        stats.submit_best(&Hash256([0x7f; 32]));
+        stats.submit_best(&Hash256(low));
+        let best = best_to_hash(stats.take_best());
+        assert_eq!(best.0[..16], low[..16]);
+        assert_eq!(best.0[16..], [0; 16]);
+        // A new window starts empty
+        assert_eq!(stats.take_best(), u128::MAX);
+    }
+}
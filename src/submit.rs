@@ -1,26 +1,62 @@
 use colored::*;
 use secp256k1::hashes::hex::DisplayHex;
+use secp256k1::ecdsa::Signature;
 use secp256k1::{All, Secp256k1};
-use secp256k1::{Message, PublicKey, SecretKey};
-use secp256k1::hashes::{sha256, Hash};
-use hex::encode;
-use reqwest::Client;
+use secp256k1::{PublicKey, SecretKey};
+use reqwest::{Client, StatusCode};
+use std::fmt;
 use std::fs;
 use std::io::Write;
 use std::path::Path;
 use std::process::Command;
-use serde::Deserialize;
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use tokio::time;
 use urlencoding::encode as uri_encode;
 
+use crate::backoff::Backoff;
+use crate::failover::ServerList;
+use crate::get_job::Job;
+use crate::hash256::{Hash256, Target};
+use crate::journal::JobJournal;
+use crate::keys::KeyStatus;
+use crate::local::LocalDaemon;
+use crate::outbox::{Outbox, PendingSubmission};
+use crate::stats::MinerStats;
+use crate::util::{is_unsupported, unix_millis};
+use crate::verify;
+
+/// Where solutions go and what happens to a mined coin, shared by every
+/// solution the miners find.
+pub struct SubmitContext {
+    pub servers: Arc<ServerList>,
+    // None in --local mode
+    pub journal: Option<Arc<JobJournal>>,
+    pub outbox: Arc<Outbox>,
+    // Set in --local mode, solutions go here instead of to the servers
+    pub local: Option<Arc<LocalDaemon>>,
+    pub rewards_dir: String,
+    pub on_mined: String,
+    pub pool_secret: String,
+    // "post", "get", or "auto" for POST with a GET fallback
+    pub submit_method: String,
+    // Indices of the servers that turned out not to take POST submissions
+    pub get_only: Mutex<HashSet<usize>>,
+}
+
+#[derive(Clone)]
 pub struct Solution {
     pub public_key: PublicKey,
     pub private_key: SecretKey,
-    pub server: String,
-    pub hash: String,
-    pub rewards_dir: String,
-    pub on_mined: String,
+    pub hash: Hash256,
+    // Seed of the job this solves
+    pub seed: String,
+    // Target of that job, None for outbox entries that didn't keep it
+    pub diff: Option<Target>,
     pub reward: f64,
-    pub pool_secret: String
+    context: Arc<SubmitContext>,
 }
 
 #[derive(Deserialize)]
@@ -28,93 +64,508 @@ struct Response {
     id: u64
 }
 
+#[derive(Serialize)]
+struct SubmitBody<'a> {
+    holder: &'a str,
+    sign: &'a str,
+    hash: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    poolsecret: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    key: Option<&'a str>,
+}
+
+/// What the daemon made of a submission.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SubmitOutcome {
+    // The coin id, None when the answer doesn't carry one (pool mode or an
+    // answer we couldn't read)
+    Accepted(Option<u64>),
+    // Someone else solved the challenge first
+    AlreadySolved,
+    // The challenge changed before the solution arrived
+    StaleSeed,
+    InvalidSignature,
+    InvalidHash,
+    RateLimited,
+    // 5xx or no answer at all
+    ServerError(String),
+    // Any other refusal, with the daemon's message
+    Rejected(String),
+    // Failed our own checks before sending, never reached the daemon
+    InternalError(String),
+}
+
+impl SubmitOutcome {
+    pub const KINDS: usize = 9;
+    pub const NAMES: [&'static str; SubmitOutcome::KINDS] = [
+        "accepted", "already solved", "stale seed", "invalid signature", "invalid hash", "rate limited", "server error", "rejected", "internal error",
+    ];
+
+    /// Index into `NAMES` and the stats counters.
+    pub fn kind(&self) -> usize {
+        match self {
+            SubmitOutcome::Accepted(_) => 0,
+            SubmitOutcome::AlreadySolved => 1,
+            SubmitOutcome::StaleSeed => 2,
+            SubmitOutcome::InvalidSignature => 3,
+            SubmitOutcome::InvalidHash => 4,
+            SubmitOutcome::RateLimited => 5,
+            SubmitOutcome::ServerError(_) => 6,
+            SubmitOutcome::Rejected(_) => 7,
+            SubmitOutcome::InternalError(_) => 8,
+        }
+    }
+
+    /// The solution stays in the outbox and is sent again later.
+    pub fn should_retry(&self) -> bool {
+        matches!(self, SubmitOutcome::RateLimited | SubmitOutcome::ServerError(_))
+    }
+
+    /// Whether the challenge can still be won after this answer, as far as we know.
+    pub fn leaves_challenge_open(&self) -> bool {
+        !matches!(self, SubmitOutcome::Accepted(_) | SubmitOutcome::AlreadySolved | SubmitOutcome::StaleSeed)
+    }
+
+    /// Reads a daemon answer. The daemon rejects with a plain text message, and
+    /// answers "invalid hash" for a hash computed with another seed too, so
+    /// `hash_matches_seed` tells a stale seed from a hash we got wrong.
+    pub fn parse(status: StatusCode, text: &str, hash_matches_seed: bool) -> SubmitOutcome {
+        if status.is_success() {
+            return match serde_json::from_str::<Response>(text) {
+                Ok(response) => SubmitOutcome::Accepted(Some(response.id)),
+                Err(_) => SubmitOutcome::Accepted(None),
+            };
+        }
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            return SubmitOutcome::RateLimited;
+        }
+        if status.is_server_error() {
+            return SubmitOutcome::ServerError(format!("{} {}", status, text.trim()));
+        }
+        let message = text.to_lowercase();
+        if message.contains("already") {
+            SubmitOutcome::AlreadySolved
+        } else if message.contains("signature") {
+            SubmitOutcome::InvalidSignature
+        } else if message.contains("hash") {
+            // Checked before the seed keywords, "invalid hash for seed" is still about the hash
+            if hash_matches_seed { SubmitOutcome::StaleSeed } else { SubmitOutcome::InvalidHash }
+        } else if message.contains("stale") || message.contains("seed") || message.contains("difficulty") {
+            SubmitOutcome::StaleSeed
+        } else {
+            SubmitOutcome::Rejected(text.trim().to_string())
+        }
+    }
+}
+
+impl fmt::Display for SubmitOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SubmitOutcome::ServerError(message) | SubmitOutcome::Rejected(message) | SubmitOutcome::InternalError(message) => write!(f, "{}: {}", SubmitOutcome::NAMES[self.kind()], message),
+            _ => f.write_str(SubmitOutcome::NAMES[self.kind()]),
+        }
+    }
+}
+
+/// Outcome counts as "2 accepted, 1 stale seed", leaving out the kinds that never happened.
+pub fn format_outcomes(counts: &[u64; SubmitOutcome::KINDS]) -> String {
+    let parts: Vec<String> = counts
+        .iter()
+        .zip(SubmitOutcome::NAMES)
+        .filter(|(count, _)| **count > 0)
+        .map(|(count, name)| format!("{} {}", count, name))
+        .collect();
+    if parts.is_empty() { String::from("none") } else { parts.join(", ") }
+}
+
+// Waits between retries of a solution no submit server answered for
+const RETRY_INITIAL: Duration = Duration::from_secs(5);
+const RETRY_MAX: Duration = Duration::from_secs(300);
+// Waits between attempts to get a solution's key on disk
+const WRITE_RETRY_INITIAL: Duration = Duration::from_secs(1);
+const WRITE_RETRY_MAX: Duration = Duration::from_secs(30);
+
 impl Solution {
-    pub async fn submit(&self, secp: &Secp256k1<All>, total_mined: &mut f64) {
-        let digest = sha256::Hash::hash(encode(self.public_key.serialize_uncompressed()).as_bytes());
-        let sign = secp.sign_ecdsa(&Message::from_digest(digest.to_byte_array()), &self.private_key);
-        let public_key_str = self.public_key.serialize_uncompressed().to_hex_string(secp256k1::hashes::hex::Case::Lower);
+    pub fn new(private_key: SecretKey, public_key: PublicKey, hash: Hash256, job: &Job, context: &Arc<SubmitContext>) -> Self {
+        Solution {
+            public_key,
+            private_key,
+            hash,
+            seed: job.seed.clone(),
+            diff: Some(job.diff),
+            reward: job.reward,
+            context: Arc::clone(context),
+        }
+    }
+
+    /// Rebuilds a solution an earlier run left in the outbox.
+    pub fn from_pending(pending: &PendingSubmission, context: &Arc<SubmitContext>) -> Result<Self, String> {
+        let (private_key, public_key, hash) = pending.keys()?;
+        Ok(Solution {
+            public_key,
+            private_key,
+            hash,
+            seed: pending.seed.clone(),
+            diff: pending.diff(),
+            reward: pending.reward,
+            context: Arc::clone(context),
+        })
+    }
+
+    /// Submits the solution and returns the first answer. Answers worth
+    /// retrying are retried in the background.
+    pub async fn submit(&self, secp: &Secp256k1<All>, stats: &Arc<MinerStats>) -> SubmitOutcome {
+        let sign = secp.sign_ecdsa(&verify::holder_message(&self.public_key), &self.private_key);
 
         println!("{} Signature: {}", "[INFO]".blue(), sign);
-        println!("{} Public key: {}", "[INFO]".blue(), public_key_str);
-        println!("{} Hash: {}", "[INFO]".blue(), self.hash);
+        println!("{} Public key: {}", "[INFO]".blue(), self.public_key_hex());
+        println!("{} Hash: {} ({} leading zero bits)", "[INFO]".blue(), self.hash, self.hash.leading_zero_bits());
         println!("{} {}", "[INFO]".blue(), "Submitting...".green());
 
-        let mut url = format!(
-            "{}/challenge-solved?holder={}&sign={}&hash={}",
-            self.server, public_key_str, sign, self.hash
-        );
+        if let Some(local) = &self.context.local {
+            let outcome = match self.preflight(secp, &sign) {
+                Ok(()) => local.accept(secp, &self.public_key, &sign, &self.hash, &self.seed),
+                Err(outcome) => outcome,
+            };
+            stats.count_outcome(&outcome);
+            if let SubmitOutcome::Accepted(_) = outcome {
+                stats.add_mined(self.reward);
+            }
+            return outcome;
+        }
 
-        if self.pool_secret != "" {
-            url = format!(
-                "{}/challenge-solved?holder={}&sign={}&hash={}&poolsecret={}&key={}",
-                self.server, public_key_str, sign, self.hash, self.pool_secret, uri_encode(&self.private_key.display_secret().to_string())
-            );
+        let pending = PendingSubmission {
+            private_key: self.private_key.display_secret().to_string(),
+            public_key: self.public_key_hex(),
+            hash: self.hash.to_string(),
+            seed: self.seed.clone(),
+            diff: self.diff.map(|diff| diff.to_string()),
+            reward: self.reward,
+            found_at: unix_millis(),
+        };
+        // A coin credited to a key that isn't on disk would be lost for good
+        let mut backoff = Backoff::new(WRITE_RETRY_INITIAL, WRITE_RETRY_MAX);
+        while let Err(e) = self.context.outbox.add(&pending) {
+            let delay = backoff.next_delay();
+            println!("{} Failed to record the key of {}, not submitting it until that works, retrying in {:.0}s: {}", "[ERROR]".red(), self.hash, delay.as_secs_f64(), e);
+            time::sleep(delay).await;
+        }
+
+        if let Some(journal) = &self.context.journal {
+            journal.submission_started(&self.seed);
+        }
+        let outcome = self.checked_send(secp, &sign).await;
+        if outcome.should_retry() {
+            // Keep retrying in the background so the miner that found it can go on
+            let solution = self.clone();
+            let stats = Arc::clone(stats);
+            let first = outcome.clone();
+            tokio::spawn(async move { solution.retry(sign, &stats, first, false).await });
+        } else {
+            self.finish(outcome.clone(), stats, false);
         }
+        outcome
+    }
+
+    /// Submits a solution an earlier run left in the outbox.
+    pub async fn resume(self, stats: Arc<MinerStats>) {
+        let secp = Secp256k1::new();
+        let sign = secp.sign_ecdsa(&verify::holder_message(&self.public_key), &self.private_key);
+        println!("{} Resubmitting {} for seed {}", "[INFO]".blue(), self.hash, self.seed);
+        let outcome = self.checked_send(&secp, &sign).await;
+        // The earlier run may have sent it and never seen the answer
+        if outcome.should_retry() {
+            self.retry(sign, &stats, outcome, true).await;
+        } else {
+            self.finish(outcome, &stats, true);
+        }
+    }
 
+    async fn retry(&self, sign: Signature, stats: &MinerStats, mut outcome: SubmitOutcome, mut uncertain: bool) {
+        let mut backoff = Backoff::new(RETRY_INITIAL, RETRY_MAX);
+        // A rate limit means the daemon didn't take it, a server error or a
+        // timeout may have been an accepted submission whose answer got lost
+        while outcome.should_retry() {
+            uncertain |= matches!(outcome, SubmitOutcome::ServerError(_));
+            let delay = backoff.next_delay();
+            println!("{} Submitting {} got {}, it stays in the outbox, retrying in {:.0}s", "[WARN]".yellow(), self.hash, outcome, delay.as_secs_f64());
+            time::sleep(delay).await;
+            outcome = self.send(&sign).await;
+        }
+        self.finish(outcome, stats, uncertain);
+    }
+
+    // Sends the solution unless it fails its own checks
+    async fn checked_send(&self, secp: &Secp256k1<All>, sign: &Signature) -> SubmitOutcome {
+        match self.preflight(secp, sign) {
+            Ok(()) => self.send(sign).await,
+            Err(outcome) => outcome,
+        }
+    }
+
+    // Makes the daemon's checks on our own output, a failure here is a
+    // miner bug and gets everything needed to track it down. Outbox entries
+    // without a target only get the signature and hash checked.
+    fn preflight(&self, secp: &Secp256k1<All>, sign: &Signature) -> Result<(), SubmitOutcome> {
+        let diff = self.diff.unwrap_or(Target::MAX);
+        let e = match verify::verify_solution(secp, &self.public_key, sign, &self.hash, &self.seed, &diff) {
+            Ok(()) => return Ok(()),
+            Err(e) => e,
+        };
+        let error = "[INTERNAL ERROR]".red().bold();
+        println!("{} Solution {} failed its own check, not submitting it: {}", error, self.hash, e);
+        println!("{} seed: {}", error, self.seed);
+        match &self.diff {
+            Some(diff) => println!("{} target: {} ({} leading zero bits)", error, diff, diff.leading_zero_bits()),
+            None => println!("{} target: unknown", error),
+        }
+        println!("{} public key: {}", error, self.public_key_hex());
+        println!("{} signature: {}", error, sign);
+        println!("{} signed message: {}", error, verify::holder_message(&self.public_key));
+        println!("{} sha256(public key + seed): {}", error, verify::solution_hash(&self.public_key, &self.seed));
+        println!("{} hash leading zero bits: {}", error, self.hash.leading_zero_bits());
+        Err(SubmitOutcome::InternalError(e.to_string()))
+    }
+
+    // Tries each submit server until one gives a definitive answer, otherwise
+    // returns what the last one said.
+    async fn send(&self, sign: &Signature) -> SubmitOutcome {
         let client = Client::builder()
-            .timeout(std::time::Duration::from_secs(5))
+            .timeout(Duration::from_secs(5))
             .build()
             .unwrap();
-        match client.get(&url).send().await {
-            Ok(res) => {
-                if res.status().is_success() {
-                    println!("{} {}\n", "[INFO]".blue(), "Successfully submitted.".green());
-                    *total_mined += self.reward;
+        let public_key_str = self.public_key_hex();
+        let hash_matches_seed = verify::solution_hash(&self.public_key, &self.seed) == self.hash;
+
+        let mut outcome = SubmitOutcome::ServerError(String::from("no submit server answered"));
+        for (index, server) in self.context.servers.candidates() {
+            let get_only = self.context.get_only.lock().unwrap().contains(&index);
+            let result = match self.context.submit_method.as_str() {
+                "get" => self.send_get(&client, &server, &public_key_str, sign).await,
+                "post" => self.send_post(&client, &server, &public_key_str, sign).await,
+                _ if get_only => self.send_get(&client, &server, &public_key_str, sign).await,
+                _ => match self.send_post(&client, &server, &public_key_str, sign).await {
+                    // Daemons from before POST submissions don't have the route
+                    Ok(res) if is_unsupported(res.status()) => {
+                        println!("{} {} doesn't take POST submissions ({}), using GET for it from now on", "[WARN]".yellow(), server, res.status());
+                        self.context.get_only.lock().unwrap().insert(index);
+                        self.send_get(&client, &server, &public_key_str, sign).await
+                    }
+                    result => result,
+                },
+            };
+
+            outcome = match result {
+                Ok(res) => {
+                    let status = res.status();
                     let text = match res.text().await {
-                        Ok(t) => t,
+                        Ok(text) => text,
                         Err(e) => {
-                            println!("{} Failed to read response text: {}", "[ERROR]".red(), e);
-                            return;
+                            println!("{} Failed to read response text: {}", "[ERROR]".red(), e.without_url());
+                            String::new()
                         }
                     };
-                    if self.pool_secret != "" {
-                        println!("{} {}\n", "[INFO]".blue(), "Submitted to pool.".green());
-                        return;
+                    let outcome = SubmitOutcome::parse(status, &text, hash_matches_seed);
+                    match &outcome {
+                        SubmitOutcome::ServerError(message) => println!("{} {} answered {}", "[ERROR]".red(), server, message),
+                        SubmitOutcome::Accepted(None) if self.context.pool_secret.is_empty() => {
+                            println!("{} No coin id in the answer", "[ERROR]".red());
+                            println!("{} {:?}\n", "[LOG]".yellow(), &text);
+                        }
+                        _ => {}
                     }
-                    match serde_json::from_str::<Response>(&text) {
-                        Ok(response) => {
-                            if !Path::new(&self.rewards_dir).exists() {
-                                let _ = fs::create_dir(&self.rewards_dir);
-                            }
-                            match fs::File::create(&format!("{}/{}.coin", &self.rewards_dir, response.id)) {
-                                Ok(mut file) => {
-                                    let _ = file.write_all(format!("{}", self.private_key.display_secret()).as_bytes());
-                                    #[cfg(target_os = "windows")]
-                                    {
-                                        let output = Command::new("cmd")
-                                            .args(&["/C", &self.on_mined.replace("%cid%", &response.id.to_string())])
-                                            .output();
-                                        println!("{} {}", "[CMD OUT]".blue(), String::from_utf8_lossy(&output.unwrap().stdout));
-                                    }
-
-                                    #[cfg(not(target_os = "windows"))]
-                                    {
-                                        let output = Command::new("sh")
-                                            .arg("-c")
-                                            .arg(&self.on_mined.replace("%cid%", &response.id.to_string()))
-                                            .output();
-                                        println!("{} {}", "[CMD OUT]".blue(), String::from_utf8_lossy(&output.unwrap().stdout));
-                                    }
-                                },
-                                Err(e) => {
-                                    println!("{} .coin file creation failed: {}\n", "[ERROR]".red(), e);
+                    outcome
+                }
+                Err(e) => {
+                    // The URL carries the signature (and the key in pool mode), keep it out of the log
+                    let e = e.without_url();
+                    println!("{} Request to {} failed: {}", "[ERROR]".red(), server, e);
+                    SubmitOutcome::ServerError(e.to_string())
+                }
+            };
+            if let SubmitOutcome::ServerError(_) = outcome {
+                self.context.servers.report_failure(index);
+                continue;
+            }
+            // A rate limit is an answer too, the server is up
+            self.context.servers.report_success(index);
+            return outcome;
+        }
+        outcome
+    }
+
+    // The original submission, everything goes into the URL
+    async fn send_get(&self, client: &Client, server: &str, public_key_str: &str, sign: &Signature) -> reqwest::Result<reqwest::Response> {
+        let mut url = format!(
+            "{}/challenge-solved?holder={}&sign={}&hash={}",
+            server, public_key_str, sign, self.hash
+        );
+
+        if !self.context.pool_secret.is_empty() {
+            url = format!(
+                "{}/challenge-solved?holder={}&sign={}&hash={}&poolsecret={}&key={}",
+                server, public_key_str, sign, self.hash, self.context.pool_secret, uri_encode(&self.private_key.display_secret().to_string())
+            );
+        }
+
+        client.get(&url).send().await
+    }
+
+    // Sends the fields as a JSON body so the signature, the pool secret and the key stay out of URLs
+    async fn send_post(&self, client: &Client, server: &str, public_key_str: &str, sign: &Signature) -> reqwest::Result<reqwest::Response> {
+        let sign = sign.to_string();
+        let hash = self.hash.to_string();
+        let key = self.private_key.display_secret().to_string();
+        let mut body = SubmitBody { holder: public_key_str, sign: &sign, hash: &hash, poolsecret: None, key: None };
+        if !self.context.pool_secret.is_empty() {
+            body.poolsecret = Some(&self.context.pool_secret);
+            body.key = Some(&key);
+        }
+        client.post(format!("{}/challenge-solved", server)).json(&body).send().await
+    }
+
+    // Handles the daemon's definitive answer and takes the solution out of the
+    // outbox. Only the final outcome is counted. `uncertain` says an earlier
+    // attempt may have reached the daemon without us getting the answer.
+    fn finish(&self, outcome: SubmitOutcome, stats: &MinerStats, uncertain: bool) {
+        stats.count_outcome(&outcome);
+        if let Some(journal) = &self.context.journal {
+            journal.submission_finished(&self.seed, matches!(outcome, SubmitOutcome::Accepted(_)));
+        }
+        let (status, coin_id) = self.handle_outcome(outcome, stats, uncertain);
+        self.context.outbox.finish(&self.hash.to_string(), status, coin_id);
+        println!("{} Submissions so far: {}", "[INFO]".blue(), format_outcomes(&stats.snapshot().outcomes));
+        print!("\n\n");
+    }
+
+    fn public_key_hex(&self) -> String {
+        self.public_key.serialize_uncompressed().to_hex_string(secp256k1::hashes::hex::Case::Lower)
+    }
+
+    // Mined coins are saved, lost races are dropped and broken solutions
+    // raise an alert. Returns the key's status and, for a mined coin, its id.
+    fn handle_outcome(&self, outcome: SubmitOutcome, stats: &MinerStats, uncertain: bool) -> (KeyStatus, Option<u64>) {
+        match outcome {
+            SubmitOutcome::Accepted(id) => {
+                println!("{} {}\n", "[INFO]".blue(), "Successfully submitted.".green());
+                stats.add_mined(self.reward);
+                if !self.context.pool_secret.is_empty() {
+                    println!("{} {}\n", "[INFO]".blue(), "Submitted to pool.".green());
+                    return (KeyStatus::Mined, None);
+                }
+                match id {
+                    Some(id) => {
+                        if !Path::new(&self.context.rewards_dir).exists() {
+                            let _ = fs::create_dir(&self.context.rewards_dir);
+                        }
+                        match fs::File::create(format!("{}/{}.coin", &self.context.rewards_dir, id)) {
+                            Ok(mut file) => {
+                                let _ = file.write_all(format!("{}", self.private_key.display_secret()).as_bytes());
+                                #[cfg(target_os = "windows")]
+                                {
+                                    let output = Command::new("cmd")
+                                        .args(["/C", &self.context.on_mined.replace("%cid%", &id.to_string())])
+                                        .output();
+                                    println!("{} {}", "[CMD OUT]".blue(), String::from_utf8_lossy(&output.unwrap().stdout));
+                                }
+
+                                #[cfg(not(target_os = "windows"))]
+                                {
+                                    let output = Command::new("sh")
+                                        .arg("-c")
+                                        .arg(self.context.on_mined.replace("%cid%", &id.to_string()))
+                                        .output();
+                                    println!("{} {}", "[CMD OUT]".blue(), String::from_utf8_lossy(&output.unwrap().stdout));
                                 }
+                            },
+                            Err(e) => {
+                                println!("{} .coin file creation failed: {}\n", "[ERROR]".red(), e);
                             }
                         }
-                        Err(e) => {
-                            println!("{} JSON deserialization failed: {}", "[ERROR]".red(), e);
-                            println!("{} {:?}\n", "[LOG]".yellow(), &text);
-                        }
+                        (KeyStatus::Mined, Some(id))
+                    }
+                    None => {
+                        println!("{} The daemon's answer didn't say which coin this is, the key stays in the key records (see `clc-miner2 keys`)", "[WARN]".yellow());
+                        (KeyStatus::Pending, None)
                     }
-                } else {
-                    let text = res.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-                    println!("{} Failed to submit, message: {}", "[ERROR]".red(), text);
                 }
             }
-            Err(e) => {
-                println!("{} Request failed: {}\n", "[ERROR]".red(), e);
+            SubmitOutcome::AlreadySolved | SubmitOutcome::StaleSeed if uncertain => {
+                // Likely solved by our own earlier attempt, which was never answered
+                println!("{} {} got {} after an attempt without an answer, the coin may be ours, the key stays in the key records (see `clc-miner2 keys`)", "[WARN]".yellow(), self.hash, outcome);
+                (KeyStatus::Pending, None)
+            }
+            SubmitOutcome::AlreadySolved | SubmitOutcome::StaleSeed => {
+                // Lost the race, nothing to do but count it
+                println!("{} Solution dropped, {}", "[WARN]".yellow(), outcome);
+                (KeyStatus::Rejected, None)
+            }
+            SubmitOutcome::InvalidSignature | SubmitOutcome::InvalidHash => {
+                // The daemon says we produced a broken solution, which is a miner bug
+                println!("{} {} rejected as {}, please report this", "[ALERT]".red().bold(), self.hash, outcome);
+                println!("{} seed: {}", "[ALERT]".red().bold(), self.seed);
+                println!("{} public key: {}", "[ALERT]".red().bold(), self.public_key_hex());
+                println!("{} sha256(public key + seed): {}", "[ALERT]".red().bold(), verify::solution_hash(&self.public_key, &self.seed));
+                (KeyStatus::Rejected, None)
+            }
+            SubmitOutcome::Rejected(message) => {
+                println!("{} Failed to submit, message: {}", "[ERROR]".red(), message);
+                (KeyStatus::Rejected, None)
             }
+            SubmitOutcome::InternalError(_) => {
+                // Never sent, preflight printed the details. The key record keeps the key.
+                (KeyStatus::Rejected, None)
+            }
+            // Never definitive, kept in the outbox by the caller
+            SubmitOutcome::RateLimited | SubmitOutcome::ServerError(_) => (KeyStatus::Pending, None),
         }
-        print!("\n\n");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(status: u16, text: &str, hash_matches_seed: bool) -> SubmitOutcome {
+        SubmitOutcome::parse(StatusCode::from_u16(status).unwrap(), text, hash_matches_seed)
+    }
+
+    #[test]
+    fn success_is_accepted_with_the_coin_id_if_given() {
+        assert_eq!(parse(200, r#"{"id":42}"#, true), SubmitOutcome::Accepted(Some(42)));
+        assert_eq!(parse(200, "ok", true), SubmitOutcome::Accepted(None));
+        assert_eq!(parse(201, "", true), SubmitOutcome::Accepted(None));
+    }
+
+    #[test]
+    fn rate_limits_and_server_errors_are_retried() {
+        assert_eq!(parse(429, "slow down", true), SubmitOutcome::RateLimited);
+        assert_eq!(parse(500, " boom \n", true), SubmitOutcome::ServerError(String::from("500 Internal Server Error boom")));
+        // Status decides before the message does
+        assert_eq!(parse(503, "invalid signature", true), SubmitOutcome::ServerError(String::from("503 Service Unavailable invalid signature")));
+        assert!(SubmitOutcome::RateLimited.should_retry());
+        assert!(SubmitOutcome::ServerError(String::new()).should_retry());
+        assert!(!SubmitOutcome::StaleSeed.should_retry());
+    }
+
+    #[test]
+    fn rejections_are_sorted_by_message() {
+        assert_eq!(parse(400, "Challenge already solved", true), SubmitOutcome::AlreadySolved);
+        assert_eq!(parse(400, "Invalid signature", true), SubmitOutcome::InvalidSignature);
+        assert_eq!(parse(400, "Stale challenge", false), SubmitOutcome::StaleSeed);
+        assert_eq!(parse(400, "Unknown seed", false), SubmitOutcome::StaleSeed);
+        assert_eq!(parse(400, "Difficulty changed", false), SubmitOutcome::StaleSeed);
+        assert_eq!(parse(403, " Banned \n", true), SubmitOutcome::Rejected(String::from("Banned")));
+    }
+
+    #[test]
+    fn hash_messages_depend_on_whether_our_hash_matches_the_seed() {
+        // A correct hash the daemon disagrees with was computed for an old seed
+        assert_eq!(parse(400, "Invalid hash", true), SubmitOutcome::StaleSeed);
+        assert_eq!(parse(400, "Invalid hash", false), SubmitOutcome::InvalidHash);
+        // Mentioning the seed doesn't make a wrong hash stale
+        assert_eq!(parse(400, "invalid hash for seed", false), SubmitOutcome::InvalidHash);
+        assert_eq!(parse(400, "invalid hash for seed", true), SubmitOutcome::StaleSeed);
     }
 }
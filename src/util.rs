@@ -0,0 +1,29 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+use reqwest::StatusCode;
+#[cfg(test)]
+use std::path::PathBuf;
+
+/// The current time in Unix milliseconds.
+pub fn unix_millis() -> u64 {
+    unix_millis_at(SystemTime::now())
+}
+
+/// `time` in Unix milliseconds, 0 for anything before the epoch.
+pub fn unix_millis_at(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |since| since.as_millis() as u64)
+}
+
+/// Older daemons answer unknown routes with one of these.
+pub fn is_unsupported(status: StatusCode) -> bool {
+    status == StatusCode::NOT_FOUND || status == StatusCode::METHOD_NOT_ALLOWED || status == StatusCode::NOT_IMPLEMENTED
+}
+
+/// An empty directory of its own for the test `name`. Tests run in parallel,
+/// and in several processes, so every name gets its own directory per run.
+#[cfg(test)]
+pub fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("clc-miner-test-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
@@ -0,0 +1,116 @@
+use std::fmt;
+
+use secp256k1::ecdsa::Signature;
+use secp256k1::hashes::{sha256, Hash};
+use secp256k1::{Message, PublicKey, Secp256k1, Verification};
+
+use crate::hash256::{Hash256, Target};
+
+#[derive(Debug)]
+pub enum VerifyError {
+    InvalidSignature,
+    // The submitted hash isn't sha256(holder + seed)
+    HashMismatch { expected: Hash256 },
+    AboveTarget,
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyError::InvalidSignature => write!(f, "invalid signature"),
+            VerifyError::HashMismatch { expected } => write!(f, "hash doesn't match sha256(holder + seed) {}", expected),
+            VerifyError::AboveTarget => write!(f, "hash is above the difficulty"),
+        }
+    }
+}
+
+/// What the daemon wants signed: sha256 of the holder's uncompressed pubkey in hex.
+pub fn holder_message(public_key: &PublicKey) -> Message {
+    let digest = sha256::Hash::hash(hex::encode(public_key.serialize_uncompressed()).as_bytes());
+    Message::from_digest(digest.to_byte_array())
+}
+
+/// sha256(holder + seed), the value compared against the diff.
+pub fn solution_hash(public_key: &PublicKey, seed: &str) -> Hash256 {
+    let holder = hex::encode(public_key.serialize_uncompressed());
+    Hash256(sha256::Hash::hash(format!("{}{}", holder, seed).as_bytes()).to_byte_array())
+}
+
+/// The checks clc-daemon makes before handing out a coin.
+pub fn verify_solution<C: Verification>(
+    secp: &Secp256k1<C>,
+    public_key: &PublicKey,
+    signature: &Signature,
+    hash: &Hash256,
+    seed: &str,
+    diff: &Target,
+) -> Result<(), VerifyError> {
+    secp.verify_ecdsa(&holder_message(public_key), signature, public_key)
+        .map_err(|_| VerifyError::InvalidSignature)?;
+    let expected = solution_hash(public_key, seed);
+    if *hash != expected {
+        return Err(VerifyError::HashMismatch { expected });
+    }
+    if !expected.meets(diff) {
+        return Err(VerifyError::AboveTarget);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use secp256k1::SecretKey;
+
+    const SEED: &str = "9de302fcc661cd5b55d186cdc579cb9e";
+
+    fn keypair(byte: u8) -> (SecretKey, PublicKey) {
+        let private_key = SecretKey::from_byte_array(&[byte; 32]).unwrap();
+        (private_key, PublicKey::from_secret_key(&Secp256k1::new(), &private_key))
+    }
+
+    #[test]
+    fn valid_solution_passes() {
+        let secp = Secp256k1::new();
+        let (private_key, public_key) = keypair(1);
+        let signature = secp.sign_ecdsa(&holder_message(&public_key), &private_key);
+        let hash = solution_hash(&public_key, SEED);
+        assert!(verify_solution(&secp, &public_key, &signature, &hash, SEED, &Target::MAX).is_ok());
+        // The target is inclusive
+        assert!(verify_solution(&secp, &public_key, &signature, &hash, SEED, &Target(hash.0)).is_ok());
+    }
+
+    #[test]
+    fn signature_by_another_key_is_rejected() {
+        let secp = Secp256k1::new();
+        let (_, public_key) = keypair(1);
+        let (other_key, _) = keypair(2);
+        let signature = secp.sign_ecdsa(&holder_message(&public_key), &other_key);
+        let hash = solution_hash(&public_key, SEED);
+        let result = verify_solution(&secp, &public_key, &signature, &hash, SEED, &Target::MAX);
+        assert!(matches!(result, Err(VerifyError::InvalidSignature)), "{:?}", result);
+    }
+
+    #[test]
+    fn hash_of_another_seed_is_rejected() {
+        let secp = Secp256k1::new();
+        let (private_key, public_key) = keypair(1);
+        let signature = secp.sign_ecdsa(&holder_message(&public_key), &private_key);
+        let hash = solution_hash(&public_key, "stale seed");
+        let result = verify_solution(&secp, &public_key, &signature, &hash, SEED, &Target::MAX);
+        match result {
+            Err(VerifyError::HashMismatch { expected }) => assert_eq!(expected, solution_hash(&public_key, SEED)),
+            _ => panic!("{:?}", result),
+        }
+    }
+
+    #[test]
+    fn hash_above_the_target_is_rejected() {
+        let secp = Secp256k1::new();
+        let (private_key, public_key) = keypair(1);
+        let signature = secp.sign_ecdsa(&holder_message(&public_key), &private_key);
+        let hash = solution_hash(&public_key, SEED);
+        let result = verify_solution(&secp, &public_key, &signature, &hash, SEED, &Target::ZERO);
+        assert!(matches!(result, Err(VerifyError::AboveTarget)), "{:?}", result);
+    }
+}
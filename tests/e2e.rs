@@ -0,0 +1,395 @@
+// Runs the miner against mock-daemon on a free port and checks what ends up
+// on disk. Each test gets its own directory with a clcminer.toml, which is
+// where the miner looks for its config.
+use std::fs;
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde_json::Value;
+
+#[allow(dead_code)]
+#[path = "../src/util.rs"]
+mod util;
+
+use util::test_dir;
+
+const MINER: &str = env!("CARGO_BIN_EXE_clc-miner2");
+const MOCK_DAEMON: &str = env!("CARGO_BIN_EXE_mock-daemon");
+// Generous, the mock daemon's default diff takes about 256 hashes per coin
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+// Kills the process when the test ends, passing or not
+struct Process(Child);
+
+impl Drop for Process {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+}
+
+fn start_mock_daemon(dir: &Path, port: u16, args: &[&str]) -> Process {
+    let log = fs::File::create(dir.join("mock-daemon.log")).unwrap();
+    let child = Command::new(MOCK_DAEMON)
+        .arg("--port")
+        .arg(port.to_string())
+        .args(args)
+        .stdout(log)
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let process = Process(child);
+    assert!(
+        wait_for(Duration::from_secs(10), || TcpStream::connect(("127.0.0.1", port)).is_ok()),
+        "mock-daemon didn't start listening on {}",
+        port
+    );
+    process
+}
+
+// Writes clcminer.toml pointing at `port` and starts the miner in `dir`.
+// Polls every second unless `extra_config` sets job_interval.
+fn start_miner(dir: &Path, port: u16, extra_config: &str, args: &[&str]) -> Process {
+    let server = format!("http://127.0.0.1:{}", port);
+    let job_interval = if extra_config.contains("job_interval") { "" } else { "job_interval = 1" };
+    let config = format!(
+        "server = \"{server}\"\n\
+         submit_server = \"{server}\"\n\
+         rewards_dir = \"rewards\"\n\
+         data_dir = \"data\"\n\
+         thread = 2\n\
+         gpu = 0\n\
+         {job_interval}\n\
+         on_mined = \"echo %cid% >> hook.log\"\n\
+         {extra_config}\n"
+    );
+    fs::write(dir.join("clcminer.toml"), config).unwrap();
+    let log = fs::File::create(dir.join("miner.log")).unwrap();
+    let child = Command::new(MINER)
+        .args(args)
+        .current_dir(dir)
+        .stdout(log)
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    Process(child)
+}
+
+fn wait_for(timeout: Duration, mut condition: impl FnMut() -> bool) -> bool {
+    let start = Instant::now();
+    while start.elapsed() < timeout {
+        if condition() {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    condition()
+}
+
+fn read(path: &Path) -> String {
+    fs::read_to_string(path).unwrap_or_default()
+}
+
+// Coin ids from rewards/{id}.coin
+fn coin_ids(dir: &Path) -> Vec<u64> {
+    let mut ids: Vec<u64> = fs::read_dir(dir.join("rewards"))
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .filter_map(|entry| entry.file_name().to_str()?.strip_suffix(".coin")?.parse().ok())
+                .collect()
+        })
+        .unwrap_or_default();
+    ids.sort();
+    ids
+}
+
+fn key_files(dir: &Path) -> Vec<PathBuf> {
+    fs::read_dir(dir.join("data/keys"))
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter(|path| path.extension().is_some_and(|extension| extension == "json"))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn key_records(dir: &Path) -> Vec<Value> {
+    key_files(dir).iter().filter_map(|path| serde_json::from_str(&read(path)).ok()).collect()
+}
+
+fn hook_ids(dir: &Path) -> Vec<u64> {
+    read(&dir.join("hook.log")).lines().filter_map(|line| line.trim().parse().ok()).collect()
+}
+
+#[test]
+fn mined_coins_are_saved_hooked_and_recorded() {
+    let dir = test_dir("mined");
+    let port = free_port();
+    let _daemon = start_mock_daemon(&dir, port, &[]);
+    let _miner = start_miner(&dir, port, "", &[]);
+
+    // The key record is finalized right after the coin file and the hook
+    let mined = wait_for(TIMEOUT, || {
+        let ids = coin_ids(&dir);
+        let records = key_records(&dir);
+        ids.len() >= 3
+            && ids.iter().all(|id| hook_ids(&dir).contains(id))
+            && ids.iter().all(|id| records.iter().any(|record| record["coin_id"] == *id))
+    });
+    assert!(mined, "no coins mined, miner log:\n{}", read(&dir.join("miner.log")));
+
+    let records = key_records(&dir);
+    for id in coin_ids(&dir) {
+        // The .coin file holds the private key the daemon credited
+        let private_key = read(&dir.join("rewards").join(format!("{}.coin", id)));
+        let record = records
+            .iter()
+            .find(|record| record["coin_id"] == id)
+            .unwrap_or_else(|| panic!("no key record for coin {}", id));
+        assert_eq!(record["status"], "mined");
+        assert_eq!(record["private_key"], private_key.trim());
+    }
+    let log = read(&dir.join("mock-daemon.log"));
+    assert!(log.contains("solved the challenge"), "mock-daemon log:\n{}", log);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        for path in key_files(&dir) {
+            let mode = fs::metadata(&path).unwrap().permissions().mode() & 0o777;
+            assert_eq!(mode, 0o600, "{} is readable by others", path.display());
+        }
+    }
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn solutions_wait_until_their_key_is_on_disk() {
+    let dir = test_dir("unwritable-keys");
+    // A file where the key directory goes makes every key write fail
+    fs::create_dir_all(dir.join("data")).unwrap();
+    fs::write(dir.join("data/keys"), "").unwrap();
+    let port = free_port();
+    let _daemon = start_mock_daemon(&dir, port, &[]);
+    let _miner = start_miner(&dir, port, "", &[]);
+
+    let blocked = wait_for(TIMEOUT, || read(&dir.join("miner.log")).contains("not submitting it until that works"));
+    assert!(blocked, "key write didn't fail, miner log:\n{}", read(&dir.join("miner.log")));
+    let log = read(&dir.join("mock-daemon.log"));
+    assert!(!log.contains("solved the challenge"), "submitted without a key record, mock-daemon log:\n{}", log);
+
+    // Once the key can be written the solution goes out
+    fs::remove_file(dir.join("data/keys")).unwrap();
+    assert!(wait_for(TIMEOUT, || !coin_ids(&dir).is_empty()), "no coin after the key write recovered");
+    // The record is finalized after the coin file and the hook
+    let recorded = wait_for(TIMEOUT, || key_records(&dir).iter().any(|record| record["status"] == "mined"));
+    assert!(recorded, "key record not marked mined, miner log:\n{}", read(&dir.join("miner.log")));
+    let _ = fs::remove_dir_all(&dir);
+}
+
+// (target, body fields) of the requests mock-daemon logged with --log-requests
+fn logged_requests(dir: &Path, method: &str, path: &str) -> Vec<(String, String)> {
+    let prefix = format!(" {} {}", method, path);
+    read(&dir.join("mock-daemon.log"))
+        .lines()
+        .filter_map(|line| {
+            let request = &line[line.find(&prefix)? + 1 + method.len() + 1..];
+            let (target, fields) = request.split_once(" body fields: ")?;
+            Some((target.to_string(), fields.to_string()))
+        })
+        .collect()
+}
+
+#[test]
+fn post_submissions_keep_secrets_out_of_the_url() {
+    let dir = test_dir("post");
+    let port = free_port();
+    let _daemon = start_mock_daemon(&dir, port, &["--log-requests"]);
+    let _miner = start_miner(&dir, port, "submit_method = \"post\"\npool_secret = \"hunter2\"", &[]);
+
+    let solved = wait_for(TIMEOUT, || read(&dir.join("mock-daemon.log")).contains("solved the challenge"));
+    assert!(solved, "nothing solved, miner log:\n{}", read(&dir.join("miner.log")));
+    let posts = logged_requests(&dir, "POST", "/challenge-solved");
+    assert!(!posts.is_empty());
+    for (target, fields) in posts {
+        assert_eq!(target, "/challenge-solved", "query string on a POST submission");
+        for field in ["sign", "poolsecret", "key"] {
+            assert!(fields.contains(field), "{} missing from the body fields [{}]", field, fields);
+        }
+    }
+    assert!(logged_requests(&dir, "GET", "/challenge-solved").is_empty(), "post mode sent a GET submission");
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn auto_falls_back_to_get_once_per_server() {
+    for status in ["404", "405", "501"] {
+        let dir = test_dir(&format!("get-only-{}", status));
+        let port = free_port();
+        let _daemon = start_mock_daemon(&dir, port, &["--log-requests", "--get-only", status]);
+        let _miner = start_miner(&dir, port, "submit_method = \"auto\"", &[]);
+
+        let mined = wait_for(TIMEOUT, || coin_ids(&dir).len() >= 3);
+        assert!(mined, "no coins mined behind a {}, miner log:\n{}", status, read(&dir.join("miner.log")));
+        // Only the first submission probes POST, the rest go straight to GET
+        assert_eq!(logged_requests(&dir, "POST", "/challenge-solved").len(), 1, "POST retried after a {}", status);
+        assert!(logged_requests(&dir, "GET", "/challenge-solved").len() >= 3);
+        assert_eq!(read(&dir.join("miner.log")).matches("doesn't take POST submissions").count(), 1);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
+
+#[test]
+fn sse_pushes_jobs_without_polling() {
+    let dir = test_dir("sse-push");
+    let port = free_port();
+    let _daemon = start_mock_daemon(&dir, port, &["--log-requests"]);
+    let _miner = start_miner(&dir, port, "job_source = \"sse\"", &[]);
+
+    // Every solve replaces the challenge, only the stream can bring the next one
+    let mined = wait_for(TIMEOUT, || coin_ids(&dir).len() >= 3);
+    assert!(mined, "no coins mined from pushed jobs, miner log:\n{}", read(&dir.join("miner.log")));
+    assert!(read(&dir.join("miner.log")).contains("Receiving jobs from"));
+    assert_eq!(logged_requests(&dir, "GET", "/challenge-events").len(), 1);
+    assert!(logged_requests(&dir, "GET", "/get-challenge").is_empty(), "sse mode polled /get-challenge");
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn sse_falls_back_to_polling() {
+    let dir = test_dir("sse-fallback");
+    let port = free_port();
+    let _daemon = start_mock_daemon(&dir, port, &["--log-requests", "--no-events"]);
+    let _miner = start_miner(&dir, port, "job_source = \"sse\"", &[]);
+
+    let mined = wait_for(TIMEOUT, || coin_ids(&dir).len() >= 3);
+    assert!(mined, "no coins mined after falling back, miner log:\n{}", read(&dir.join("miner.log")));
+    assert!(read(&dir.join("miner.log")).contains("falling back to polling"));
+    // The missing stream is found once, from then on only polls
+    assert_eq!(logged_requests(&dir, "GET", "/challenge-events").len(), 1);
+    assert!(!logged_requests(&dir, "GET", "/get-challenge").is_empty());
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn sse_reconnects_dropped_streams() {
+    let dir = test_dir("sse-reconnect");
+    let port = free_port();
+    let _daemon = start_mock_daemon(&dir, port, &["--drop-streams", "2"]);
+    let _miner = start_miner(&dir, port, "job_source = \"sse\"", &[]);
+
+    let opened = || read(&dir.join("mock-daemon.log")).matches("Event stream opened").count();
+    assert!(wait_for(TIMEOUT, || opened() >= 3), "stream not reopened, miner log:\n{}", read(&dir.join("miner.log")));
+    // Jobs keep coming over the new stream
+    let before = coin_ids(&dir).len();
+    let mined = wait_for(TIMEOUT, || coin_ids(&dir).len() >= before + 2);
+    assert!(mined, "no coins after reconnecting, miner log:\n{}", read(&dir.join("miner.log")));
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn turned_down_solutions_resume_the_challenge() {
+    let dir = test_dir("sse-turned-down");
+    let port = free_port();
+    let _daemon = start_mock_daemon(&dir, port, &["--reject-first", "2"]);
+    let _miner = start_miner(&dir, port, "job_source = \"sse\"", &[]);
+
+    // The daemon keeps the challenge and pushes nothing, the miner has to pick it up again
+    let mined = wait_for(TIMEOUT, || !coin_ids(&dir).is_empty());
+    assert!(mined, "stuck after a turned down solution, miner log:\n{}", read(&dir.join("miner.log")));
+    assert_eq!(read(&dir.join("miner.log")).matches("is still open, mining it again").count(), 2);
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn network_solves_are_picked_up_between_polls() {
+    let dir = test_dir("challenge-check");
+    let port = free_port();
+    // Too hard to solve here, every new challenge comes from the rotation
+    let hard = "0000000000ffffffffffffffffffffffffffffffffffffffffffffffffffffff";
+    let _daemon = start_mock_daemon(&dir, port, &["--diff", hard, "--rotate", "2"]);
+    let _miner = start_miner(&dir, port, "job_interval = 3600\nchallenge_check_interval = 1", &[]);
+
+    // With an hour between polls only the challenge check can find them
+    let moved = wait_for(TIMEOUT, || read(&dir.join("miner.log")).matches("was solved by the network, moving on to").count() >= 2);
+    let log = read(&dir.join("miner.log"));
+    assert!(moved, "rotations not picked up, miner log:\n{}", log);
+    assert!(log.matches("New job").count() >= 3);
+    assert!(!log.contains("is down") && !log.contains("Error fetching job"), "miner log:\n{}", log);
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn lost_answers_keep_the_key_listed() {
+    let dir = test_dir("lost-answer");
+    let port = free_port();
+    let _daemon = start_mock_daemon(&dir, port, &["--drop-replies", "1"]);
+    let _miner = start_miner(&dir, port, "", &[]);
+
+    // The daemon credits the coin and hangs up, the retry then hears it's already solved
+    let holder = || {
+        read(&dir.join("mock-daemon.log"))
+            .lines()
+            .find_map(|line| Some(line.split_once("Dropping the reply for coin ")?.1.split_once(" to ")?.1.to_string()))
+    };
+    let retried = wait_for(TIMEOUT, || holder().is_some() && read(&dir.join("miner.log")).contains("the coin may be ours"));
+    assert!(retried, "no retry after the lost answer, miner log:\n{}", read(&dir.join("miner.log")));
+    let holder = holder().unwrap();
+    assert!(read(&dir.join("mock-daemon.log")).contains("Late solution for a solved challenge"));
+
+    let records = key_records(&dir);
+    let record = records
+        .iter()
+        .find(|record| record["public_key"] == holder.as_str())
+        .unwrap_or_else(|| panic!("no key record for {}", holder));
+    assert_eq!(record["status"], "pending");
+    let keys = Command::new(MINER).arg("keys").current_dir(&dir).output().unwrap();
+    let listed = String::from_utf8_lossy(&keys.stdout);
+    assert!(listed.contains(&holder), "key not listed by `keys`:\n{}", listed);
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn local_mode_mines_without_a_daemon() {
+    let dir = test_dir("local");
+    // Nothing listens here, local mode must not need it
+    let port = free_port();
+    let easy = "00ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff";
+    let _miner = start_miner(&dir, port, "", &["--local", "--local-diff", easy]);
+
+    let accepted = || read(&dir.join("miner.log")).matches("Accepted locally.").count();
+    assert!(wait_for(TIMEOUT, || accepted() >= 2), "nothing accepted locally, miner log:\n{}", read(&dir.join("miner.log")));
+    let log = read(&dir.join("miner.log"));
+    assert!(!log.contains("Local solution rejected"), "miner log:\n{}", log);
+    assert!(!log.contains("is down") && !log.contains("Error fetching job"), "local mode went to the network, miner log:\n{}", log);
+    // Local coins aren't real, nothing is saved as one or journaled next to real jobs
+    assert!(coin_ids(&dir).is_empty());
+    assert!(!dir.join("data/jobs.jsonl").exists(), "local jobs written to the job journal");
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn network_solves_are_picked_up_with_the_defaults() {
+    // Default polling, where the challenge check doesn't run, and pushed jobs
+    for (name, extra_config) in [("poll", ""), ("sse", "job_source = \"sse\"")] {
+        let dir = test_dir(&format!("network-solve-{}", name));
+        let port = free_port();
+        let hard = "0000000000ffffffffffffffffffffffffffffffffffffffffffffffffffffff";
+        let _daemon = start_mock_daemon(&dir, port, &["--diff", hard, "--rotate", "2"]);
+        let _miner = start_miner(&dir, port, extra_config, &[]);
+
+        let moved = wait_for(TIMEOUT, || read(&dir.join("miner.log")).matches("was solved by the network, moving on to").count() >= 2);
+        assert!(moved, "{}: rotations not reported, miner log:\n{}", name, read(&dir.join("miner.log")));
+        let _ = fs::remove_dir_all(&dir);
+    }
+}